@@ -0,0 +1,145 @@
+[bits 16]
+[org 600h]
+
+; hard disk master boot record, loads boot sector of the active partition
+
+; partition table location after relocation
+PART_TABLE_ADDR equ 7beh
+PART_ENTRY_SIZE equ 16
+PART_ACTIVE_FLAG equ 80h
+
+BOOT_SECTOR_ADDR equ 7c00h
+
+READ_RETRIES equ 5
+
+[section .text]
+
+cli
+xor ax, ax
+mov ds, ax
+mov es, ax
+mov ss, ax
+mov sp, BOOT_SECTOR_ADDR
+sti
+
+; moving ourselves out of the way to make room for partition boot sector
+mov si, BOOT_SECTOR_ADDR
+mov di, 600h
+mov cx, 256
+rep movsw
+
+jmp 0:relocated
+
+relocated:
+mov [boot_drive], dl
+
+; checking INT 13h extensions presence
+mov ah, 41h
+mov bx, 55aah
+int 13h
+jc no_ext_fail
+
+cmp bx, 0aa55h
+jne no_ext_fail
+
+; looking for active partition
+mov si, PART_TABLE_ADDR
+mov cx, 4
+
+find_active:
+cmp byte [si], PART_ACTIVE_FLAG
+je active_found
+
+add si, PART_ENTRY_SIZE
+loop find_active
+
+mov si, no_part_msg
+jmp fail
+
+active_found:
+; partition start LBA
+mov eax, [si + 8]
+mov [dap_lba], eax
+
+mov di, READ_RETRIES
+
+read_retry:
+; ds:si should point to active partition entry when control is passed to the boot sector
+push si
+
+mov si, dap
+mov ah, 42h
+mov dl, [boot_drive]
+int 13h
+
+pop si
+jnc read_done
+
+; resetting disk system before the next attempt
+xor ah, ah
+int 13h
+
+dec di
+jnz read_retry
+
+mov si, io_err_msg
+jmp fail
+
+read_done:
+cmp word [BOOT_SECTOR_ADDR + 510], 0aa55h
+jne no_os_fail
+
+mov dl, [boot_drive]
+jmp 0:BOOT_SECTOR_ADDR
+
+no_ext_fail:
+mov si, no_ext_msg
+jmp fail
+
+no_os_fail:
+mov si, no_os_msg
+
+; prints error message pointed by si and halts
+fail:
+lodsb
+or al, al
+jz $
+
+mov ah, 0eh
+mov bx, 7
+int 10h
+
+jmp fail
+
+code_size equ $ - $$
+
+[section .data]
+
+; disk address packet ( single sector to 0:7c00h )
+dap:
+db 10h, 0
+dw 1
+dw BOOT_SECTOR_ADDR, 0
+dap_lba:
+dd 0, 0
+
+io_err_msg:
+db 'I/O error', 0
+
+no_ext_msg:
+db 'no LBA support', 0
+
+no_part_msg:
+db 'no active partition', 0
+
+no_os_msg:
+db 'no boot sector', 0
+
+data_size equ $ - $$
+
+[section .bss]
+boot_drive:
+resb 1
+
+; negative TIMES value fails the build if variables reach partition table (sections are dword aligned)
+times PART_TABLE_ADDR - 600h - ((code_size + 3) & ~3) - ((data_size + 3) & ~3) - ($ - $$) resb 1
@@ -40,8 +40,13 @@ data_code_seg:
 # base high byte
 .byte 0
 
-.equ SYS_IMAGE_ADDR, 0x10000
+.equ SYS_IMAGE_ADDR, 0x100000
 .equ SYS_STACK_TOP, 0x30000 - 4
+# should be kept in sync with boot sector
+.equ BOOT_DRIVE_ADDR, 0x500
+# boot configuration sector loaded right after the loader
+.equ CMDLINE_ADDR, 0x9f000
+.equ CMDLINE_MAX_LEN, 512
 
 .section .text
 
@@ -88,6 +93,14 @@ movl %eax, %cr0
 # configuring large stack for system code
 movl $SYS_STACK_TOP, %esp
 
+# passing command line ( making sure it's null terminated )
+movb $0, CMDLINE_ADDR + CMDLINE_MAX_LEN - 1
+pushl $CMDLINE_ADDR
+
+# passing boot drive number stored by boot sector
+movzbl BOOT_DRIVE_ADDR, %eax
+pushl %eax
+
 # configuring on stack BssInfo { addr, size } struct representation
 pushl %ecx
 pushl %edi
@@ -1,135 +1,302 @@
 [bits 16]
 [org 7c3eh]
 
+; FAT12 BIOS parameter block fields (filled by mkdosfs)
+%define bpb_sec_per_clus byte [7c0dh]
+%define bpb_rsvd_secs word [7c0eh]
+%define bpb_num_fats byte [7c10h]
+%define bpb_root_ents word [7c11h]
+%define bpb_secs_per_fat word [7c16h]
+%define bpb_secs_per_trk word [7c18h]
+%define bpb_num_heads word [7c1ah]
+%define bpb_hidden_secs dword [7c1ch]
+
+; 2nd stage loader is stored in reserved sectors following boot sector
+LOADER_SEG equ 9e00h
+LOADER_SECS equ 8
+; boot configuration ( system command line ) sector follows 2nd stage loader
+CMDLINE_SECS equ 1
+
+; root directory and FAT are loaded right after boot sector
+DISK_BUF_ADDR equ 7e00h
+
+; system image clusters are loaded to 64k bounce buffer and then moved to 1M
+CLUS_BUF_SEG equ 1000h
+SYS_IMAGE_ADDR equ 100000h
+
+; extended memory block move descriptors table location
+MOVE_GDT_ADDR equ 600h
+
+READ_RETRIES equ 5
+
+; boot drive number is passed to 2nd stage loader using fixed memory location
+BOOT_DRIVE_ADDR equ 500h
+
 [section .text]
 
+cli
+xor ax, ax
+mov ds, ax
+mov es, ax
+mov ss, ax
+mov sp, 7c00h
+sti
+
+; BIOS (or partition table loader) passes boot drive number in dl
+mov [BOOT_DRIVE_ADDR], dl
+
 ; opening A20 line to make all RAM accessible (disable 1MB wraparound)
 in al, 92h
 or al, 2
 out 92h, al
 
-mov di, 0
+; loading 2nd stage loader ( assuming it's not larger than 4k ) and boot configuration
+push LOADER_SEG
+pop es
+xor bx, bx
+xor eax, eax
+inc ax
+mov cx, LOADER_SECS + CMDLINE_SECS
+call read_sectors
+
+; root directory location ( reserved sectors + all FAT copies )
+movzx eax, bpb_num_fats
+mul bpb_secs_per_fat
+add ax, bpb_rsvd_secs
+
+; root directory size in sectors ( 16 entries per sector )
+mov cx, bpb_root_ents
+shr cx, 4
+
+; data area starts right after root directory
+mov [data_start], ax
+add [data_start], cx
+
+push ds
+pop es
+mov bx, DISK_BUF_ADDR
+call read_sectors
+
+; looking for system image file entry
+mov di, DISK_BUF_ADDR
+mov dx, bpb_root_ents
+
+find_file:
+mov si, sys_file_name
+mov cx, 11
+push di
+repe cmpsb
+pop di
+je file_found
 
-%macro read_sectors 5
+add di, 32
+dec dx
+jnz find_file
 
-; head number
-push %5
-; how many sectors to read
-push %4
-; MBR is the first sector - skipping 
-; start sector
-push %3
-; track number
-push %2
-; read buffer address ( location is ES:<buf addr> )
-push %1
+mov si, no_file_msg
+jmp fail
 
-call read
+file_found:
+; saving first cluster number
+push word [di + 1ah]
 
-; removing arguments from the stack
-add sp, 10
+; replacing root directory with the first FAT copy
+movzx eax, bpb_rsvd_secs
+mov cx, bpb_secs_per_fat
+mov bx, DISK_BUF_ADDR
+call read_sectors
 
-%endmacro
+pop ax
 
-mov ax, 9e00h
-mov es, ax
+mov ebp, SYS_IMAGE_ADDR
 
-; loading 2nd stage loader ( assuming it's not larger than 4k )
-; read(char* buffer, size_t track_num, size_t start_sector, size_t sector_count, size_t head_num)
-read_sectors 0h, 0, 2, 8, 0
+load_cluster:
+push ax
 
-; moving system binary image load buffer segment register at 64k
-mov ax, 1000h
-mov es, ax
+; cluster location ( data start + (cluster - 2) * sectors per cluster )
+dec ax
+dec ax
+movzx cx, bpb_sec_per_clus
+mul cx
+add ax, [data_start]
+movzx eax, ax
 
-; reading system binary image sectors
-read_sectors 0h, 0, 1, 18, 1
+push CLUS_BUF_SEG
+pop es
+xor bx, bx
+call read_sectors
 
-read_sectors 2400h, 1, 1, 18, 0
+call move_cluster
 
-read_sectors 4800h, 1, 1, 18, 1
+pop ax
 
-read_sectors 6c00h, 2, 1, 18, 0
+; getting next cluster number from 12 bit FAT entry
+mov si, ax
+shr si, 1
+add si, ax
+mov dx, [DISK_BUF_ADDR + si]
 
-read_sectors 9000h, 2, 1, 18, 1
+test al, 1
+jz even_cluster
+shr dx, 4
 
-read_sectors 0b400h, 3, 1, 18, 0
+even_cluster:
+xchg ax, dx
+and ax, 0fffh
 
-read_sectors 0d800h, 3, 1, 18, 1
+; 0ff8h - 0fffh marks the end of cluster chain
+cmp ax, 0ff8h
+jb load_cluster
 
-mov ax, 1fc0h
-mov es, ax
+; jumping to second stage loader
+cli
 
-read_sectors 0h, 4, 1, 2, 0
+; loading global memory segment descriptors table
+lgdt [gdt_info]
 
-mov ax, 2000h
+; activating protected mode
+mov eax, cr0
+or al, 1
+mov cr0, eax
+
+; here long jump to boot loader should be encoded directly in data section
+jmp loader_long_jump
+
+; move_cluster(ebp = destination address), moves cluster from bounce buffer to extended memory
+move_cluster:
+xor ax, ax
 mov es, ax
 
-read_sectors 0h, 4, 3, 16, 0
+mov di, MOVE_GDT_ADDR
+mov cx, 24
+rep stosw
 
-; jumping to second stage loader
-jmp loader_jmp
+; segment limits are 0ffffh
+dec ax
+
+; source descriptor (64k bounce buffer)
+mov [MOVE_GDT_ADDR + 10h], ax
+mov dword [MOVE_GDT_ADDR + 12h], 93010000h
+
+; destination descriptor
+mov [MOVE_GDT_ADDR + 18h], ax
+; ( system image should fit below 16M, base high byte is left zeroed )
+mov eax, ebp
+mov [MOVE_GDT_ADDR + 1ah], eax
+mov byte [MOVE_GDT_ADDR + 1dh], 93h
+
+; words to move
+movzx cx, bpb_sec_per_clus
+shl cx, 8
+
+; advancing destination address
+movzx eax, cx
+shl eax, 1
+add ebp, eax
+
+mov si, MOVE_GDT_ADDR
+mov ah, 87h
+int 15h
+jc io_fail
 
-; read(char* buffer, size_t track_num, size_t start_sector, size_t sector_count, size_t head_num)
-read:
-; performing stack frame setup
-push bp
+ret
+
+; read_sectors(eax = volume sector number, cx = sector count, es:bx = buffer)
+; on return eax points to the sector following the last one and bx is advanced
+read_sectors:
+pushad
+
+add eax, bpb_hidden_secs
+
+mov di, READ_RETRIES
+
+read_retry:
+pushad
 mov bp, sp
 
-; allocate stack space for locals here if needed
+; hard disks are accessed using LBA extensions
+test byte [BOOT_DRIVE_ADDR], 80h
+jnz read_lba
 
-; saving callee safe registers
+; converting sector number to CHS address
+xor edx, edx
+movzx ecx, bpb_secs_per_trk
+div ecx
+
+; sector numbers start from 1
+inc dx
+mov cl, dl
+
+xor dx, dx
+div bpb_num_heads
+
+; cylinder number (bits 8-9 are passed in cl bits 6-7)
+mov ch, al
+shl ah, 6
+or cl, ah
+; head number
+mov dh, dl
+
+; read single sector BIOS service
+mov ax, 0201h
+jmp read_call
+
+read_lba:
+; disk address packet ( single sector at LBA in eax to es:bx )
+push dword 0
+push eax
+push es
 push bx
-push si
-push di
+push word 1
+push word 10h
+mov si, sp
+
+; extended read BIOS service
+mov ah, 42h
 
-; load specified number of sectors to specified buffer
-; load sectors BIOS service
-mov ah, 02h
-; using sector_count parameter
-mov al, byte [bp+10]
-; read buffer address parameter
-mov bx, [bp+4]
-
-; starting sector number parameter
-mov cl, byte [bp+8]
-; track number 0 
-mov ch, byte [bp+6]
-; using head number parameter
-mov dh, byte [bp+12]
-; using drive number 0
-mov dl, 0
-
-; performing call to BIOS service
+read_call:
+mov dl, [BOOT_DRIVE_ADDR]
 int 13h
+jnc read_ok
 
-; restoring callee safe registers
-pop di
-pop si
-pop bx
+; resetting disk system before the next attempt
+xor ah, ah
+int 13h
+stc
 
-; destroying stack frame
+read_ok:
 mov sp, bp
-pop bp
+popad
+jnc read_done
 
-; returning
-ret
+dec di
+jnz read_retry
 
-; here long jump to boot loader should be encoded directly in data section
+io_fail:
+mov si, io_err_msg
 
-loader_jmp:
+; prints error message pointed by si and halts
+fail:
+lodsb
+or al, al
+jz $
 
-cli
+mov ah, 0eh
+mov bx, 7
+int 10h
 
-; loading global memory segment descriptors table
-lgdt [gdt_info]
+jmp fail
 
-; activating protected mode
-mov eax, cr0
-or eax, 1
-mov cr0, eax
+read_done:
+popad
 
-jmp loader_long_jump
+inc eax
+add bx, 512
+loop read_sectors
+
+ret
+
+code_size equ $ - $$
 
 [section .data]
 loader_long_jump:
@@ -145,3 +312,22 @@ dw 08h
 gdt_info:
 dw 17h
 dd 9e000h
+
+sys_file_name:
+db 'UOS     ELF'
+
+io_err_msg:
+db 'I/O error', 0
+
+no_file_msg:
+db 'no UOS.ELF', 0
+
+data_size equ $ - $$
+
+[section .bss]
+data_start:
+resw 1
+
+; negative TIMES value fails the build if variables reach disk buffer (sections are dword aligned),
+; code and data can't overlap boot sector signature then
+times DISK_BUF_ADDR - 7c3eh - ((code_size + 3) & ~3) - ((data_size + 3) & ~3) - ($ - $$) resb 1
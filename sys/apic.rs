@@ -0,0 +1,203 @@
+use core::ptr;
+
+use crate::pio;
+use crate::vm;
+use crate::timer;
+
+#[link(name = "uos")]
+extern {
+	fn cpuid(leaf: u32, regs: *mut [u32; 4]);
+
+	fn read_msr(msr: u32) -> u64;
+
+	fn write_msr(msr: u32, lo: u32, hi: u32);
+}
+
+// CPUID leaf 1 EDX on-chip APIC presence bit
+const CPUID_FEATURES_LEAF: u32 = 1;
+const CPUID_EDX_APIC_BIT: u32 = 1 << 9;
+
+const IA32_APIC_BASE_MSR: u32 = 0x1b;
+const APIC_BASE_ENABLE_BIT: u32 = 1 << 11;
+const APIC_BASE_ADDR_MASK: u32 = 0xfffff000;
+
+// local APIC registers offsets
+const LAPIC_ID_REG: usize = 0x20;
+const LAPIC_TPR_REG: usize = 0x80;
+const LAPIC_EOI_REG: usize = 0xb0;
+const LAPIC_SVR_REG: usize = 0xf0;
+const LAPIC_LVT_TIMER_REG: usize = 0x320;
+const LAPIC_TIMER_INIT_CNT_REG: usize = 0x380;
+const LAPIC_TIMER_CURR_CNT_REG: usize = 0x390;
+const LAPIC_TIMER_DIV_REG: usize = 0x3e0;
+
+const LAPIC_SVR_ENABLE_BIT: u32 = 1 << 8;
+
+// lower 4 bits should be set
+pub const SPURIOUS_VEC_NUM: usize = 0x3f;
+
+const LVT_MASKED_BIT: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC_BIT: u32 = 1 << 17;
+
+// timer counter decremented every 16 bus clocks
+const LAPIC_TIMER_DIV_16: u32 = 0x3;
+
+// I/O APIC is assumed to be at the standard address (MP and ACPI tables are not parsed)
+const IOAPIC_BASE_ADDR: usize = 0xfec00000;
+
+const IOAPIC_REGSEL: usize = 0x0;
+const IOAPIC_WIN: usize = 0x10;
+
+const IOAPIC_REDIR_TBL_REG: u32 = 0x10;
+
+// ISA IRQ 0 is connected to I/O APIC input 2 (interrupt source override used by PC compatible systems)
+const ISA_IRQ0_IOAPIC_PIN: usize = 2;
+
+const ISA_IRQ_CNT: usize = 16;
+
+// timer tick IRQ, local APIC timer is used instead of PIT
+const TIMER_IRQ: usize = 0;
+
+// PIT channel 2 is used for local APIC timer calibration
+const PIT_CH2_IOPORT_NUM: u32 = 0x42;
+const PIT_CMD_IOPORT_NUM: u32 = 0x43;
+const PIT_CH2_GATE_IOPORT_NUM: u32 = 0x61;
+
+// channel 2, low/high byte access, interrupt on terminal count mode
+const PIT_CH2_ONE_SHOT_CMD: u32 = 0xb0;
+const PIT_CH2_GATE_BIT: u32 = 0x1;
+const PIT_CH2_SPEAKER_BIT: u32 = 0x2;
+const PIT_CH2_OUT_BIT: u32 = 0x20;
+
+const PIT_FREQ: u32 = 1193182;
+const CALIBRATION_MS: u32 = 10;
+
+static mut LAPIC_BASE_ADDR: usize = 0;
+
+static mut IRQ_VEC_BASE: u32 = 0;
+
+pub fn is_present() -> bool {
+	let mut regs = [0u32; 4];
+
+	unsafe {
+		cpuid(CPUID_FEATURES_LEAF, &mut regs);
+	}
+
+	regs[3] & CPUID_EDX_APIC_BIT != 0
+}
+
+// legacy PICs should be remapped and masked, ISA IRQs are mapped to vectors starting from irq_vec_base
+pub unsafe fn init(irq_vec_base: usize) -> bool {
+	let apic_base = read_msr(IA32_APIC_BASE_MSR);
+	let apic_base_lo = apic_base as u32;
+
+	let lapic_addr = (apic_base_lo & APIC_BASE_ADDR_MASK) as usize;
+
+	if !vm::map_mmio(lapic_addr) || !vm::map_mmio(IOAPIC_BASE_ADDR) {
+		return false
+	}
+
+	// globally enabling local APIC
+	write_msr(IA32_APIC_BASE_MSR, apic_base_lo | APIC_BASE_ENABLE_BIT, (apic_base >> 32) as u32);
+
+	LAPIC_BASE_ADDR = lapic_addr;
+	IRQ_VEC_BASE = irq_vec_base as u32;
+
+	// accepting all interrupts
+	lapic_write(LAPIC_TPR_REG, 0);
+	lapic_write(LAPIC_SVR_REG, LAPIC_SVR_ENABLE_BIT | SPURIOUS_VEC_NUM as u32);
+
+	// all ISA IRQs are routed to the current processor, masked until claimed
+	for irq in 0..ISA_IRQ_CNT {
+		set_irq_masked(irq, true);
+	}
+
+	init_timer();
+
+	true
+}
+
+pub fn eoi() {
+	unsafe {
+		lapic_write(LAPIC_EOI_REG, 0);
+	}
+}
+
+pub fn set_irq_masked(irq: usize, masked: bool) {
+	if irq >= ISA_IRQ_CNT {
+		return
+	}
+
+	unsafe {
+		let mask_bit = if masked { LVT_MASKED_BIT } else { 0 };
+
+		if irq == TIMER_IRQ {
+			let lvt = lapic_read(LAPIC_LVT_TIMER_REG) & !LVT_MASKED_BIT;
+			lapic_write(LAPIC_LVT_TIMER_REG, lvt | mask_bit);
+
+			return
+		}
+
+		// the pin is connected to PIT which is not used (IRQ 2 is cascade line of legacy PICs)
+		if irq == ISA_IRQ0_IOAPIC_PIN {
+			return
+		}
+
+		let redir_reg = IOAPIC_REDIR_TBL_REG + 2 * irq as u32;
+
+		// edge triggered, active high, fixed delivery to the current processor
+		let apic_id = lapic_read(LAPIC_ID_REG) >> 24;
+
+		ioapic_write(redir_reg + 1, apic_id << 24);
+		ioapic_write(redir_reg, (IRQ_VEC_BASE + irq as u32) | mask_bit);
+	}
+}
+
+// local APIC timer generates scheduling ticks with the same vector as PIT IRQ
+unsafe fn init_timer() {
+	lapic_write(LAPIC_TIMER_DIV_REG, LAPIC_TIMER_DIV_16);
+
+	let ticks_per_ms = calibrate_timer();
+
+	lapic_write(LAPIC_LVT_TIMER_REG, (IRQ_VEC_BASE + TIMER_IRQ as u32) | LVT_TIMER_PERIODIC_BIT | LVT_MASKED_BIT);
+	lapic_write(LAPIC_TIMER_INIT_CNT_REG, ticks_per_ms * 1000 / timer::TICK_HZ);
+}
+
+// counts local APIC timer ticks during PIT channel 2 one shot period
+unsafe fn calibrate_timer() -> u32 {
+	let pit_cnt = PIT_FREQ / 1000 * CALIBRATION_MS;
+
+	// enabling channel 2 gate with speaker disconnected
+	let gate = pio::in_byte(PIT_CH2_GATE_IOPORT_NUM) & !PIT_CH2_SPEAKER_BIT;
+	pio::out_byte(gate & !PIT_CH2_GATE_BIT, PIT_CH2_GATE_IOPORT_NUM);
+
+	pio::out_byte(PIT_CH2_ONE_SHOT_CMD, PIT_CMD_IOPORT_NUM);
+	pio::out_byte(pit_cnt & 0xff, PIT_CH2_IOPORT_NUM);
+	pio::out_byte(pit_cnt >> 8, PIT_CH2_IOPORT_NUM);
+
+	// counting starts on gate rising edge
+	pio::out_byte(gate | PIT_CH2_GATE_BIT, PIT_CH2_GATE_IOPORT_NUM);
+	lapic_write(LAPIC_TIMER_INIT_CNT_REG, 0xffffffff);
+
+	while pio::in_byte(PIT_CH2_GATE_IOPORT_NUM) & PIT_CH2_OUT_BIT == 0 {}
+
+	let elapsed = 0xffffffff - lapic_read(LAPIC_TIMER_CURR_CNT_REG);
+
+	lapic_write(LAPIC_TIMER_INIT_CNT_REG, 0);
+	pio::out_byte(gate & !PIT_CH2_GATE_BIT, PIT_CH2_GATE_IOPORT_NUM);
+
+	elapsed / CALIBRATION_MS
+}
+
+unsafe fn lapic_read(reg: usize) -> u32 {
+	ptr::read_volatile((LAPIC_BASE_ADDR + reg) as *const u32)
+}
+
+unsafe fn lapic_write(reg: usize, val: u32) {
+	ptr::write_volatile((LAPIC_BASE_ADDR + reg) as *mut u32, val);
+}
+
+unsafe fn ioapic_write(reg: u32, val: u32) {
+	ptr::write_volatile((IOAPIC_BASE_ADDR + IOAPIC_REGSEL) as *mut u32, reg);
+	ptr::write_volatile((IOAPIC_BASE_ADDR + IOAPIC_WIN) as *mut u32, val);
+}
@@ -1,87 +1,18 @@
 .section .data
 
 .equ CODE_SEG_SEL, 0x8
-# fault handler descriptor
-.equ INTR_GATE, 0x8e00
-# trap handler descriptor
-.equ TRAP_GATE, 0x8f00
+.equ DATA_SEG_SEL, 0x10
 # this size should be kept in sync with HLL code
-.equ TASK_CPU_STATE_STRUCT_SIZE, 44
-
-# interrupt vector definitions start
-.align 8
-
-idt_start:
-
-# 0. divide error fault
-.short isr0
-.short CODE_SEG_SEL
-.short INTR_GATE
-.short 0x0
-
-# 1. reserved handler descriptor
-.short isr1
-.short CODE_SEG_SEL
-.short INTR_GATE
-.short 0x0
-
-# 2. NMI handler
-.short isr2
-.short CODE_SEG_SEL
-.short INTR_GATE
-.short 0x0
-
-# 3. breakpoint trap
-.short isr3
-.short CODE_SEG_SEL
-.short TRAP_GATE
-.short 0x0
-
-# 4. overflow trap
-.short isr4
-.short CODE_SEG_SEL
-.short TRAP_GATE
-.short 0x0
-
-# filling standard protected mode inerrupt handlers
-.irp n, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31
-.short isr\n
-.short CODE_SEG_SEL
-.short INTR_GATE
-.short 0x0
-.endr
-
-# IRQ interrupts
-.irp n, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47
-.short isr\n
-.short CODE_SEG_SEL
-.short INTR_GATE
-.short 0x0
-.endr
-
-# switch task syscall handler
-.short switch_task
-.short CODE_SEG_SEL
-# privilege level should be fixed to make accessible from user space
-.short INTR_GATE
-.short 0x0
-
-idt_end:
-
-idt_limit = idt_end - idt_start - 1
-
-idt_info:
-.short idt_limit
-.int idt_start
+.equ TASK_CPU_STATE_STRUCT_SIZE, 56
+.equ SYSCALL_VEC_NUM, 0x80
 
 .global SCR_BUF
 SCR_BUF:
 .int 0xb8000
 
-intr_handlers:
-.rept 48
-.int nop_intr_handler
-.endr
+.global apic_spurious_count
+apic_spurious_count:
+.int 0
 
 .section .text
 
@@ -109,56 +40,22 @@ popl %ebp
 
 .endm
 
+# interrupt entry point, dummy error code is pushed if processor doesn't push it
+# to get the same frame layout for all vectors
 .macro ISR vecnum
 isr\vecnum:
 
-# saving registers
-pushl %eax
-pushl %ecx
-pushl %edx
+.if (\vecnum == 8) | ((\vecnum >= 10) & (\vecnum <= 14)) | (\vecnum == 17) | (\vecnum == 21) | (\vecnum == 29) | (\vecnum == 30)
+.else
+pushl $0
+.endif
+pushl $\vecnum
 
-# interrupt handler table index
-movl $\vecnum, %eax
-# interrupt handler call
-call *intr_handlers(, %eax, 4)
-
-# restoring registers
-popl %edx
-popl %ecx
-popl %eax
-
-iret
+jmp isr_entry
 .endm
 
-# interrupt service routine for faults with error code
-.macro ISRE vecnum
-isr\vecnum:
-
-# saving registers
-pushl %eax
-pushl %ecx
-pushl %edx
-
-# placing error code copy on the top of the stack
-pushl 12(%esp)
-
-# interrupt handler table index
-movl $\vecnum, %eax
-# interrupt handler call
-call *intr_handlers(, %eax, 4)
-
-# removing error code copy from the stack
-addl $4, %esp
-
-# restoring registers
-popl %edx
-popl %ecx
-popl %eax
-
-# removing error code from the stack
-addl $4, %esp
-
-iret
+.macro ISR_ADDR vecnum
+.int isr\vecnum
 .endm
 
 .global get_sp
@@ -167,6 +64,11 @@ movl %esp, %eax
 addl $4, %eax
 ret
 
+.global get_ebp
+get_ebp:
+movl %ebp, %eax
+ret
+
 .global get_eflags
 get_eflags:
 pushfl
@@ -179,31 +81,6 @@ movl $0, %eax
 movw %cs, %ax
 ret
 
-.global register_handler
-register_handler:
-
-# interrupt vector number
-movl 4(%esp), %edx
-# pointer to interrupt handler function
-movl 8(%esp), %eax
-
-movl %eax, intr_handlers(, %edx, 4)
-
-ret
-
-# above function copy for HLL type safety
-.global register_handler_with_err_code
-register_handler_with_err_code:
-
-# interrupt vector number
-movl 4(%esp), %edx
-# pointer to interrupt handler function
-movl 8(%esp), %eax
-
-movl %eax, intr_handlers(, %edx, 4)
-
-ret
-
 .global out_byte
 out_byte:
 
@@ -237,10 +114,121 @@ destroy_stack_frame
 
 ret
 
+.global load_gdt
+load_gdt:
+
+# pointer to GDT limit and base
+movl 4(%esp), %eax
+lgdt (%eax)
+
+# reloading code segment register
+ljmp $CODE_SEG_SEL, $reload_data_segs
+
+reload_data_segs:
+movw $DATA_SEG_SEL, %ax
+
+movw %ax, %ds
+movw %ax, %ss
+movw %ax, %es
+movw %ax, %gs
+movw %ax, %fs
+
+ret
+
+.global load_tr
+load_tr:
+
+# TSS selector
+movl 4(%esp), %eax
+ltr %ax
+
+ret
+
+.global cpuid
+cpuid:
+
+create_stack_frame
+
+# leaf number
+movl 8(%ebp), %eax
+xorl %ecx, %ecx
+cpuid
+
+# storing eax, ebx, ecx, edx to the array passed as the second parameter
+movl 12(%ebp), %edi
+movl %eax, (%edi)
+movl %ebx, 4(%edi)
+movl %ecx, 8(%edi)
+movl %edx, 12(%edi)
+
+destroy_stack_frame
+
+ret
+
+# 64 bit value is returned in edx:eax
+.global read_msr
+read_msr:
+
+movl 4(%esp), %ecx
+rdmsr
+
+ret
+
+.global write_msr
+write_msr:
+
+# MSR number, low and high value parts
+movl 4(%esp), %ecx
+movl 8(%esp), %eax
+movl 12(%esp), %edx
+wrmsr
+
+ret
+
+.global get_cr0
+get_cr0:
+movl %cr0, %eax
+ret
+
+.global get_cr2
+get_cr2:
+movl %cr2, %eax
+ret
+
+.global get_cr4
+get_cr4:
+movl %cr4, %eax
+ret
+
+.global get_cr3
+get_cr3:
+movl %cr3, %eax
+ret
+
+.global set_cr3
+set_cr3:
+
+# page directory base address
+movl 4(%esp), %eax
+movl %eax, %cr3
+
+ret
+
+.global invalidate_page
+invalidate_page:
+
+# virtual address of the page
+movl 4(%esp), %eax
+invlpg (%eax)
+
+ret
+
 .global load_idt
 load_idt:
 
-lidt idt_info
+# pointer to IDT limit and base
+movl 4(%esp), %eax
+lidt (%eax)
 
 ret
 
@@ -251,8 +239,28 @@ sti
 
 ret
 
-# default interrupt handling fuction
-nop_intr_handler:
+.global intr_disable
+intr_disable:
+
+cli
+
+ret
+
+# stops processor until reset
+.global halt
+halt:
+
+cli
+hlt
+
+jmp halt
+
+# stops processor until the next interrupt
+.global wait_intr
+wait_intr:
+
+sti
+hlt
 
 ret
 
@@ -263,61 +271,45 @@ int $48
 
 ret
 
-# interrupt service routine gates
-ISR 0
-ISR 1
-ISR 2
-ISR 3
-ISR 4
-ISR 5
-ISR 6
-ISR 7
-ISRE 8
-ISR 9
-ISRE 10
-ISRE 11
-ISRE 12
-ISRE 13
-ISRE 14
-ISR 15
-ISR 16
-ISR 17
-ISR 18
-ISR 19
-ISR 20
-ISR 21
-ISR 22
-ISR 23
-ISR 24
-ISR 25
-ISR 26
-ISR 27
-ISR 28
-ISR 29
-ISR 30
-ISR 31
-
-ISR 32
-ISR 33
-ISR 34
-ISR 35
-ISR 36
-ISR 37
-ISR 38
-ISR 39
-ISR 40
-ISR 41
-ISR 42
-ISR 43
-ISR 44
-ISR 45
-ISR 46
-ISR 47
+# interrupt stubs for all vectors, IDT is filled by HLL code
+.altmacro
+
+.set vec_num, 0
+.rept 256
+ISR %vec_num
+.set vec_num, vec_num + 1
+.endr
+
+.noaltmacro
+
+.section .data
+
+# interrupt stubs addresses indexed by vector number
+.global isr_stubs
+isr_stubs:
+.altmacro
+
+.set vec_num, 0
+.rept 256
+ISR_ADDR %vec_num
+.set vec_num, vec_num + 1
+.endr
+
+.noaltmacro
 
+.section .text
+
+.global switch_task
 switch_task:
 
 # saving cpu state
 pushal
+pushl %ds
+
+# task could be interrupted in user mode
+movw $DATA_SEG_SEL, %ax
+movw %ax, %ds
+movw %ax, %es
 
 # passing stack pointer with saved current task state
 pushl %esp
@@ -325,7 +317,7 @@ call save_current_task_state
 addl $4, %esp
 
 # switch to new task and get new stack pointer prepared for restore
-# moved 32 bytes down
+# moved 36 bytes down
 call switch_task_and_get_new_stack_ptr
 
 # restoring stack
@@ -339,7 +331,103 @@ addl $4, %esp
 # call print_task_state
 
 # restoring registers
+popl %eax
+movw %ax, %ds
+movw %ax, %es
+movw %ax, %fs
+movw %ax, %gs
+
 popal
 
 # switching to new task completely
 iret
+
+# common interrupt handling code, frame contains saved registers,
+# vector number, error code and interrupt frame
+isr_entry:
+
+pushal
+pushl %ds
+
+movw $DATA_SEG_SEL, %ax
+movw %ax, %ds
+movw %ax, %es
+
+# passing pointer to the frame
+pushl %esp
+call isr_dispatch
+addl $4, %esp
+
+popl %eax
+movw %ax, %ds
+movw %ax, %es
+movw %ax, %fs
+movw %ax, %gs
+
+popal
+
+# removing vector number and error code
+addl $8, %esp
+
+iret
+
+.global apic_spurious
+# spurious interrupts should not be acknowledged, only counted
+apic_spurious:
+
+incl apic_spurious_count
+
+iret
+
+# double fault task entry point, error code is pushed to the task stack
+.global double_fault_task
+double_fault_task:
+
+call double_fault_handler
+
+jmp halt
+
+.global syscall_entry
+# system call entry point, call number is passed in eax and arguments in ebx, ecx, edx, esi, edi
+syscall_entry:
+
+# saving registers in the same layout as switch_task does
+pushal
+pushl %ds
+
+movw $DATA_SEG_SEL, %ax
+movw %ax, %ds
+movw %ax, %es
+
+# passing pointer to saved registers, result is stored to saved eax
+pushl %esp
+call syscall_dispatch
+addl $4, %esp
+
+popl %eax
+movw %ax, %ds
+movw %ax, %es
+movw %ax, %fs
+movw %ax, %gs
+
+popal
+
+iret
+
+# user mode test task, increments counter and yields processor
+.section .user, "awx"
+
+.global user_test_task
+user_test_task:
+
+incl user_test_counter
+
+# yield system call
+movl $0, %eax
+int $SYSCALL_VEC_NUM
+
+jmp user_test_task
+
+.global user_test_counter
+user_test_counter:
+.int 0
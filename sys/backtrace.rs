@@ -0,0 +1,106 @@
+use core::mem;
+use core::slice;
+use core::str;
+
+use crate::vm;
+
+#[link(name = "uos")]
+extern {
+	fn get_ebp() -> usize;
+
+	// symbol table boundaries (defined by linker script)
+	static __ksyms_start: u8;
+
+	static __ksyms_end: u8;
+}
+
+const MAX_FRAMES: usize = 16;
+
+// prints call chain of the current function
+pub fn print_current() {
+	unsafe {
+		let ebp = get_ebp();
+
+		// return address of the current function is the first frame
+		if is_valid_frame(ebp) {
+			print(*((ebp + 4) as *const usize), *(ebp as *const usize));
+		}
+	}
+}
+
+// prints frames starting from the specified instruction following ebp chain
+// (frame contains saved ebp followed by return address)
+pub fn print(eip: usize, ebp: usize) {
+	console_println!("backtrace:");
+
+	print_frame(0, eip);
+
+	let mut frame = ebp;
+
+	for i in 1..MAX_FRAMES {
+		if !is_valid_frame(frame) {
+			break;
+		}
+
+		let (prev_frame, ret_addr) = unsafe {
+			(*(frame as *const usize), *((frame + 4) as *const usize))
+		};
+
+		if ret_addr == 0 {
+			break;
+		}
+
+		print_frame(i, ret_addr);
+
+		// stack grows down, caller frame is located higher
+		if prev_frame <= frame {
+			break;
+		}
+
+		frame = prev_frame;
+	}
+}
+
+// kernel stacks are located in the shared identity mapped area
+fn is_valid_frame(ebp: usize) -> bool {
+	ebp >= vm::PAGE_SIZE && ebp < vm::USER_SPACE_START - 8 && ebp & (mem::size_of::<usize>() - 1) == 0
+}
+
+fn print_frame(num: usize, addr: usize) {
+	match resolve(addr) {
+		Some((name, offset)) => console_println!("  [{}] {:08x} {}+{:#x}", num, addr, name, offset),
+		_ => console_println!("  [{}] {:08x} ?", num, addr)
+	}
+}
+
+// symbol table records are sorted by address: address, function name terminated by zero and padding
+pub fn resolve(addr: usize) -> Option<(&'static str, usize)> {
+	unsafe {
+		let start = &__ksyms_start as *const u8 as usize;
+		let end = &__ksyms_end as *const u8 as usize;
+
+		let ksyms = slice::from_raw_parts(start as *const u8, end - start);
+
+		let mut found = None;
+
+		let mut pos = 0;
+		while pos + mem::size_of::<u32>() <= ksyms.len() {
+			let sym_addr = *(ksyms.as_ptr().add(pos) as *const u32) as usize;
+			if sym_addr == 0 || sym_addr > addr {
+				break;
+			}
+
+			pos += mem::size_of::<u32>();
+
+			let name_len = ksyms[pos..].iter().position(|b| *b == 0).unwrap_or(ksyms.len() - pos);
+			let name = str::from_utf8(&ksyms[pos..pos + name_len]).unwrap_or("?");
+
+			found = Some((name, addr - sym_addr));
+
+			// skipping name, terminating zero and padding
+			pos = (pos + name_len + 1 + 3) & !3;
+		}
+
+		found
+	}
+}
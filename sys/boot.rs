@@ -0,0 +1,257 @@
+use core::ptr;
+use core::mem;
+
+use crate::string as ustr;
+use crate::pio;
+
+const MULTIBOOT_INFO_MEM: u32 = 0x1;
+const MULTIBOOT_INFO_BOOTDEV: u32 = 0x2;
+const MULTIBOOT_INFO_CMDLINE: u32 = 0x4;
+const MULTIBOOT_INFO_MODS: u32 = 0x8;
+const MULTIBOOT_INFO_MMAP: u32 = 0x40;
+
+// boot page tables identity map the first 4mb only
+const IDENTITY_MAPPED_END: usize = 0x400000;
+
+// extended memory size in kb detected by BIOS (stage 2 loader doesn't provide memory map)
+const CMOS_ADDR_PORT: u32 = 0x70;
+const CMOS_DATA_PORT: u32 = 0x71;
+const CMOS_EXT_MEM_LOW_REG: u32 = 0x30;
+const CMOS_EXT_MEM_HIGH_REG: u32 = 0x31;
+
+const MAX_MEM_REGIONS: usize = 16;
+const MAX_MODULES: usize = 8;
+
+pub const MEM_REGION_AVAILABLE: u32 = 1;
+
+// multiboot information structure (only the fields we are interested in)
+#[repr(C)]
+pub struct MultibootInfo {
+	flags: u32,
+	mem_lower: u32,
+	mem_upper: u32,
+	boot_device: u32,
+	cmdline: u32,
+	mods_count: u32,
+	mods_addr: u32,
+	syms: [u32; 4],
+	mmap_length: u32,
+	mmap_addr: u32
+}
+
+// memory map entry, size field doesn't include itself
+#[repr(C, packed)]
+struct MultibootMmapEntry {
+	size: u32,
+	base_addr: u64,
+	length: u64,
+	kind: u32
+}
+
+#[repr(C)]
+struct MultibootModule {
+	mod_start: u32,
+	mod_end: u32,
+	string: u32,
+	reserved: u32
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BootLoader {
+	Stage2,
+	Multiboot
+}
+
+#[derive(Clone, Copy)]
+pub struct MemRegion {
+	pub base: u64,
+	pub len: u64,
+	pub kind: u32
+}
+
+#[derive(Clone, Copy)]
+pub struct Module {
+	pub start: usize,
+	pub end: usize,
+	name: *const u8
+}
+
+impl Module {
+	pub fn name(&self) -> &'static str {
+		if self.name.is_null() {
+			""
+		} else {
+			unsafe {
+				ustr::from_cstr(self.name)
+			}
+		}
+	}
+
+	pub fn size(&self) -> usize {
+		self.end - self.start
+	}
+}
+
+pub struct BootInfo {
+	pub loader: BootLoader,
+	// lower and upper memory size in kb
+	pub mem_lower: usize,
+	pub mem_upper: usize,
+	// BIOS drive number ( 0x80 and above for hard disks )
+	pub boot_drive: Option<u8>,
+	cmdline: *const u8,
+	mem_regions: [MemRegion; MAX_MEM_REGIONS],
+	mem_regions_cnt: usize,
+	modules: [Module; MAX_MODULES],
+	modules_cnt: usize
+}
+
+impl BootInfo {
+	pub fn cmdline(&self) -> &'static str {
+		if self.cmdline.is_null() {
+			""
+		} else {
+			unsafe {
+				ustr::from_cstr(self.cmdline)
+			}
+		}
+	}
+
+	pub fn mem_regions(&self) -> &[MemRegion] {
+		&self.mem_regions[..self.mem_regions_cnt]
+	}
+
+	pub fn modules(&self) -> &[Module] {
+		&self.modules[..self.modules_cnt]
+	}
+}
+
+const NULL_MEM_REGION: MemRegion = MemRegion { base: 0, len: 0, kind: 0 };
+const NULL_MODULE: Module = Module { start: 0, end: 0, name: ptr::null() };
+
+// filled once during system startup and never changed after that
+static mut BOOT_INFO: BootInfo = BootInfo {
+	loader: BootLoader::Stage2,
+	mem_lower: 0,
+	mem_upper: 0,
+	boot_drive: None,
+	cmdline: ptr::null(),
+	mem_regions: [NULL_MEM_REGION; MAX_MEM_REGIONS],
+	mem_regions_cnt: 0,
+	modules: [NULL_MODULE; MAX_MODULES],
+	modules_cnt: 0
+};
+
+pub fn info() -> &'static BootInfo {
+	unsafe {
+		&*ptr::addr_of!(BOOT_INFO)
+	}
+}
+
+pub unsafe fn init_stage2(boot_drive: u8, cmdline: *const u8) {
+	BOOT_INFO.loader = BootLoader::Stage2;
+	BOOT_INFO.boot_drive = Some(boot_drive);
+	BOOT_INFO.cmdline = cmdline;
+	BOOT_INFO.mem_upper = (read_cmos(CMOS_EXT_MEM_HIGH_REG) << 8 | read_cmos(CMOS_EXT_MEM_LOW_REG)) as usize;
+}
+
+unsafe fn read_cmos(reg: u32) -> u32 {
+	pio::out_byte(reg, CMOS_ADDR_PORT);
+	pio::in_byte(CMOS_DATA_PORT)
+}
+
+// loader can place its structures anywhere, the ones outside of identity mapped area are ignored
+fn is_mapped(addr: usize, size: usize) -> bool {
+	match addr.checked_add(size) {
+		Some(end) => end <= IDENTITY_MAPPED_END,
+		_ => false
+	}
+}
+
+// returns null if string terminator isn't found in identity mapped area
+unsafe fn mapped_cstr(addr: usize) -> *const u8 {
+	if addr == 0 {
+		return ptr::null()
+	}
+
+	let mut p = addr;
+	while p < IDENTITY_MAPPED_END {
+		if *(p as *const u8) == 0 {
+			return addr as *const u8
+		}
+
+		p += 1;
+	}
+
+	ptr::null()
+}
+
+pub unsafe fn init_multiboot(mbi: *const MultibootInfo) {
+	let boot_info = &mut *ptr::addr_of_mut!(BOOT_INFO);
+
+	boot_info.loader = BootLoader::Multiboot;
+
+	if !is_mapped(mbi as usize, mem::size_of::<MultibootInfo>()) {
+		return
+	}
+
+	let mbi = &*mbi;
+
+	if mbi.flags & MULTIBOOT_INFO_MEM != 0 {
+		boot_info.mem_lower = mbi.mem_lower as usize;
+		boot_info.mem_upper = mbi.mem_upper as usize;
+	}
+
+	if mbi.flags & MULTIBOOT_INFO_BOOTDEV != 0 {
+		// drive number is stored in the most significant byte
+		boot_info.boot_drive = Some((mbi.boot_device >> 24) as u8);
+	}
+
+	if mbi.flags & MULTIBOOT_INFO_CMDLINE != 0 {
+		boot_info.cmdline = mapped_cstr(mbi.cmdline as usize);
+	}
+
+	if mbi.flags & MULTIBOOT_INFO_MMAP != 0 && is_mapped(mbi.mmap_addr as usize, mbi.mmap_length as usize) {
+		let mut entry_addr = mbi.mmap_addr as usize;
+		let mmap_end = entry_addr + mbi.mmap_length as usize;
+
+		while entry_addr + mem::size_of::<MultibootMmapEntry>() <= mmap_end && boot_info.mem_regions_cnt < MAX_MEM_REGIONS {
+			let entry: MultibootMmapEntry = ptr::read_unaligned(entry_addr as *const MultibootMmapEntry);
+
+			boot_info.mem_regions[boot_info.mem_regions_cnt] = MemRegion {
+				base: entry.base_addr,
+				len: entry.length,
+				kind: entry.kind
+			};
+			boot_info.mem_regions_cnt += 1;
+
+			// entry size doesn't include size field itself
+			entry_addr = entry_addr.saturating_add(entry.size as usize).saturating_add(4);
+		}
+	}
+
+	let mods_size = (mbi.mods_count as usize).checked_mul(mem::size_of::<MultibootModule>()).unwrap_or(usize::MAX);
+
+	if mbi.flags & MULTIBOOT_INFO_MODS != 0 && is_mapped(mbi.mods_addr as usize, mods_size) {
+		let mods = mbi.mods_addr as *const MultibootModule;
+
+		for i in 0..(mbi.mods_count as usize) {
+			if boot_info.modules_cnt == MAX_MODULES {
+				break;
+			}
+
+			let m = &*mods.wrapping_add(i);
+
+			if m.mod_end < m.mod_start || !is_mapped(m.mod_start as usize, (m.mod_end - m.mod_start) as usize) {
+				continue
+			}
+
+			boot_info.modules[boot_info.modules_cnt] = Module {
+				start: m.mod_start as usize,
+				end: m.mod_end as usize,
+				name: mapped_cstr(m.string as usize)
+			};
+			boot_info.modules_cnt += 1;
+		}
+	}
+}
@@ -1,30 +1,125 @@
 use core::fmt;
+use core::cmp;
+use core::ptr;
 
 use crate::task;
 use crate::ring;
 use crate::pio;
 use crate::vec;
+use crate::param;
+use crate::serial;
+use crate::lock;
+use crate::string as ustr;
 
 #[link(name = "uos")]
 extern {
 	// external linkage for screen buffer memory area making compiler happy 
 	// (mutable pointer can't be shared between threads safely)
-	static SCR_BUF: *mut [u8; 3840];
+	static SCR_BUF: *mut [u16; SCREEN_SIZE];
 }
 
-static mut SCR_WRITER: ScreenWriter = ScreenWriter { pos: 0 };
+// output is serialized, lock holder runs with interrupts disabled
+static SCR_WRITER: lock::IrqMutex<ScreenWriter> = lock::IrqMutex::new(ScreenWriter { pos: 0, attr: DEFAULT_ATTR, view_offset: 0, esc_state: EscState::None,
+	esc_params: [0; MAX_ESC_PARAMS], esc_param_idx: 0, vga: true, serial: false });
+
+// console output device: vga, serial or all
+static CONSOLE_OUTPUT: param::Param<&'static str> = param!("console", &'static str, "vga");
 
 const SCREEN_COLS: usize = 80;
-// re-mappped BIOS data area location
-const BIOS_DATA_AREA_ADDR: usize = 0x40400;
+const SCREEN_ROWS: usize = 25;
+const SCREEN_SIZE: usize = SCREEN_COLS * SCREEN_ROWS;
+
+const TAB_SIZE: usize = 8;
+
+// lines scrolled off the top of the screen
+const SCROLLBACK_ROWS: usize = 256;
+
+// VGA text mode palette
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(u8)]
+pub enum Color {
+	Black = 0,
+	Blue = 1,
+	Green = 2,
+	Cyan = 3,
+	Red = 4,
+	Magenta = 5,
+	Brown = 6,
+	LightGrey = 7,
+	DarkGrey = 8,
+	LightBlue = 9,
+	LightGreen = 10,
+	LightCyan = 11,
+	LightRed = 12,
+	LightMagenta = 13,
+	Yellow = 14,
+	White = 15
+}
+
+// background colors are limited to the first 8 (attribute bit 7 is blink)
+pub const fn attr(fg: Color, bg: Color) -> u8 {
+	((bg as u8 & 0x7) << 4) | fg as u8
+}
+
+const DEFAULT_ATTR: u8 = attr(Color::LightGrey, Color::Black);
+const PANIC_ATTR: u8 = attr(Color::White, Color::Red);
+
+// foreground intensity bit
+const BRIGHT_ATTR_BIT: u8 = 0x8;
+
+const ESC: u8 = 0x1b;
+
+// line editing control characters
+const CTRL_A: u8 = 0x01;
+const CTRL_E: u8 = 0x05;
+const BACKSPACE: u8 = 0x08;
+const CTRL_K: u8 = 0x0b;
+const CTRL_U: u8 = 0x15;
+const CTRL_W: u8 = 0x17;
+const DEL: u8 = 0x7f;
+
+const HISTORY_SIZE: usize = 32;
+
+// CSI sequence parameters, i.e. ESC [ row ; col H
+const MAX_ESC_PARAMS: usize = 4;
+
+// ANSI color index to VGA color
+const ANSI_COLORS: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
 
-const VIDEO_PAGE_0_CURSOR_POS_ADDR: usize = BIOS_DATA_AREA_ADDR + 80;
+#[derive(Clone, Copy, PartialEq)]
+enum EscState {
+	None,
+	// ESC received
+	Esc,
+	// ESC [ received, parameters are parsed
+	Csi
+}
 
 pub static KBD_BUF: ring::RingBuf = ring::RingBuf::new();
 
-// TODO make this object thread safe
+// scrollback lines ring, SCROLLBACK_HEAD is the next line to be written
+static mut SCROLLBACK: [[u16; SCREEN_COLS]; SCROLLBACK_ROWS] = [[0; SCREEN_COLS]; SCROLLBACK_ROWS];
+static mut SCROLLBACK_HEAD: usize = 0;
+static mut SCROLLBACK_LEN: usize = 0;
+
+// live screen contents while scrollback is shown
+static mut SAVED_SCREEN: [u16; SCREEN_SIZE] = [0; SCREEN_SIZE];
+
+// lines entered using read_line, the oldest first
+static HISTORY: lock::Mutex<vec::Vec<vec::Vec<u8>>> = lock::Mutex::new(vec::Vec::new());
+
 pub struct ScreenWriter {
-	pos: usize
+	pos: usize,
+	// VGA character attribute (colors)
+	attr: u8,
+	// number of scrollback lines shown above the live screen (0 - live screen is shown)
+	view_offset: usize,
+	// escape sequence parser state
+	esc_state: EscState,
+	esc_params: [u16; MAX_ESC_PARAMS],
+	esc_param_idx: usize,
+	vga: bool,
+	serial: bool
 }
 
 impl ScreenWriter {
@@ -35,45 +130,273 @@ impl ScreenWriter {
 	}
 
 	unsafe fn write_char(&mut self, chr: u8) {
-		if chr == b'\n' {
-			let next_line_offset = SCREEN_COLS - (self.pos % SCREEN_COLS);
-			self.pos += next_line_offset;
-		} else {
-			(*SCR_BUF)[self.pos*2] = chr;
-			self.pos += 1;
+		if self.serial {
+			if chr == b'\n' {
+				serial::write_byte(b'\r');
+			}
+
+			serial::write_byte(chr);
+		}
+
+		if !self.vga {
+			return
+		}
+
+		// any output returns to the live screen
+		if self.view_offset > 0 {
+			self.set_view_offset(0);
+		}
+
+		if chr == ESC || self.esc_state != EscState::None {
+			self.parse_esc(chr);
+			self.move_cursor();
+
+			return
+		}
+
+		let col = self.pos % SCREEN_COLS;
+
+		match chr {
+			b'\n' => self.pos += SCREEN_COLS - col,
+			b'\r' => self.pos -= col,
+			b'\t' => {
+				for _ in 0..(TAB_SIZE - col % TAB_SIZE) {
+					self.put_char(b' ');
+				}
+			},
+			// backspace only moves cursor, "\x08 \x08" erases previous character
+			BACKSPACE => if col > 0 {
+				self.pos -= 1;
+			},
+			_ => self.put_char(chr)
+		}
+
+		// writing the last screen cell doesn't scroll the screen until the next character is put
+		while self.pos > SCREEN_SIZE || (self.pos == SCREEN_SIZE && chr == b'\n') {
+			self.scroll();
 		}
 
 		self.move_cursor();
 	}
 
-	unsafe fn clear(&mut self) {
-		for (i, b) in (*SCR_BUF).iter_mut().enumerate() {
-			*b = if (i & 0x1) == 1 {
-				0x7
-			} else {
-				0x20
+	unsafe fn put_char(&mut self, chr: u8) {
+		if self.pos >= SCREEN_SIZE {
+			self.scroll();
+		}
+
+		(*SCR_BUF)[self.pos] = (self.attr as u16) << 8 | chr as u16;
+		self.pos += 1;
+	}
+
+	// moves cursor n cells back, unlike backspace VGA cursor is moved to the previous lines
+	unsafe fn cursor_back(&mut self, n: usize) {
+		if self.serial {
+			for _ in 0..n {
+				serial::write_byte(BACKSPACE);
+			}
+		}
+
+		if !self.vga {
+			return
+		}
+
+		if self.view_offset > 0 {
+			self.set_view_offset(0);
+		}
+
+		self.pos = self.pos.saturating_sub(n);
+
+		self.move_cursor();
+	}
+
+	// screen cell is character in low byte and attribute in high byte
+	fn blank_cell(&self) -> u16 {
+		(self.attr as u16) << 8 | 0x20
+	}
+
+	// fills cells range with spaces using current attribute
+	unsafe fn erase(&mut self, from: usize, to: usize) {
+		let blank = self.blank_cell();
+
+		for cell in (&mut *SCR_BUF)[from..to].iter_mut() {
+			*cell = blank;
+		}
+	}
+
+	// VT100 subset: SGR colors, cursor movement, erase in display and erase in line
+	unsafe fn parse_esc(&mut self, chr: u8) {
+		match self.esc_state {
+			// ESC drops unfinished sequence and starts a new one
+			_ if chr == ESC => {
+				self.esc_state = EscState::Esc;
+				self.esc_params = [0; MAX_ESC_PARAMS];
+				self.esc_param_idx = 0;
+			},
+			EscState::None => {},
+			EscState::Esc => {
+				// other escape sequences are ignored
+				self.esc_state = if chr == b'[' { EscState::Csi } else { EscState::None };
+			},
+			EscState::Csi => match chr {
+				b'0'..=b'9' => {
+					let p = &mut self.esc_params[self.esc_param_idx];
+					*p = p.saturating_mul(10).saturating_add((chr - b'0') as u16);
+				},
+				b';' => {
+					// extra parameters overwrite the last one
+					if self.esc_param_idx < MAX_ESC_PARAMS - 1 {
+						self.esc_param_idx += 1;
+					}
+				},
+				// private mode marker, i.e. ESC [ ? 25 l
+				b'?' => {},
+				// final byte
+				0x40..=0x7e => {
+					self.exec_csi(chr);
+					self.esc_state = EscState::None;
+				},
+				_ => self.esc_state = EscState::None
 			}
 		}
+	}
+
+	unsafe fn exec_csi(&mut self, cmd: u8) {
+		let row = self.pos / SCREEN_COLS;
+		let col = self.pos % SCREEN_COLS;
+
+		// missing or zero count means 1
+		let n = cmp::max(self.esc_params[0] as usize, 1);
+
+		match cmd {
+			b'A' => self.pos = row.saturating_sub(n) * SCREEN_COLS + col,
+			b'B' => self.pos = cmp::min(row + n, SCREEN_ROWS - 1) * SCREEN_COLS + col,
+			b'C' => self.pos = row * SCREEN_COLS + cmp::min(col + n, SCREEN_COLS - 1),
+			b'D' => self.pos = row * SCREEN_COLS + col.saturating_sub(n),
+			// 1-based row and column
+			b'H' | b'f' => {
+				let r = cmp::min(cmp::max(self.esc_params[0] as usize, 1), SCREEN_ROWS) - 1;
+				let c = cmp::min(cmp::max(self.esc_params[1] as usize, 1), SCREEN_COLS) - 1;
+
+				self.pos = r * SCREEN_COLS + c;
+			},
+			// cursor position is not changed by erase commands
+			b'J' => match self.esc_params[0] {
+				0 => self.erase(self.pos, SCREEN_SIZE),
+				1 => self.erase(0, self.pos + 1),
+				_ => self.erase(0, SCREEN_SIZE)
+			},
+			b'K' => match self.esc_params[0] {
+				0 => self.erase(self.pos, (row + 1) * SCREEN_COLS),
+				1 => self.erase(row * SCREEN_COLS, self.pos + 1),
+				_ => self.erase(row * SCREEN_COLS, (row + 1) * SCREEN_COLS)
+			},
+			b'm' => {
+				for i in 0..=self.esc_param_idx {
+					self.set_graphic_rendition(self.esc_params[i]);
+				}
+			},
+			_ => {}
+		}
+	}
+
+	fn set_graphic_rendition(&mut self, p: u16) {
+		let fg = self.attr & 0xf;
+		let bg = self.attr & 0xf0;
+
+		self.attr = match p {
+			0 => DEFAULT_ATTR,
+			// bold is shown as bright foreground
+			1 => self.attr | BRIGHT_ATTR_BIT,
+			22 => self.attr & !BRIGHT_ATTR_BIT,
+			30..=37 => bg | (fg & BRIGHT_ATTR_BIT) | ANSI_COLORS[(p - 30) as usize],
+			39 => bg | (fg & BRIGHT_ATTR_BIT) | (DEFAULT_ATTR & 0x7),
+			40..=47 => (ANSI_COLORS[(p - 40) as usize] << 4) | fg,
+			49 => (DEFAULT_ATTR & 0xf0) | fg,
+			90..=97 => bg | BRIGHT_ATTR_BIT | ANSI_COLORS[(p - 90) as usize],
+			_ => self.attr
+		};
+	}
+
+	// moves screen contents one line up, top line goes to scrollback
+	unsafe fn scroll(&mut self) {
+		let scr = &mut *SCR_BUF;
+
+		SCROLLBACK[SCROLLBACK_HEAD].copy_from_slice(&scr[..SCREEN_COLS]);
+		SCROLLBACK_HEAD = (SCROLLBACK_HEAD + 1) % SCROLLBACK_ROWS;
+
+		if SCROLLBACK_LEN < SCROLLBACK_ROWS {
+			SCROLLBACK_LEN += 1;
+		}
+
+		scr.copy_within(SCREEN_COLS.., 0);
+
+		let blank = self.blank_cell();
+
+		for cell in scr[SCREEN_SIZE - SCREEN_COLS..].iter_mut() {
+			*cell = blank;
+		}
+
+		self.pos -= SCREEN_COLS;
+	}
+
+	// shows screen scrolled back by specified number of lines
+	unsafe fn set_view_offset(&mut self, offset: usize) {
+		let offset = cmp::min(offset, SCROLLBACK_LEN);
+		if offset == self.view_offset {
+			return
+		}
+
+		if self.view_offset == 0 {
+			(*ptr::addr_of_mut!(SAVED_SCREEN)).copy_from_slice(&*SCR_BUF);
+		}
+
+		self.view_offset = offset;
+
+		let scr = &mut *SCR_BUF;
+
+		// scrollback lines are followed by the live screen lines
+		for row in 0..SCREEN_ROWS {
+			let line = SCROLLBACK_LEN - offset + row;
+
+			let src = if line < SCROLLBACK_LEN {
+				&SCROLLBACK[(SCROLLBACK_HEAD + SCROLLBACK_ROWS - SCROLLBACK_LEN + line) % SCROLLBACK_ROWS][..]
+			} else {
+				let live_row = line - SCROLLBACK_LEN;
+				&SAVED_SCREEN[live_row * SCREEN_COLS..(live_row + 1) * SCREEN_COLS]
+			};
+
+			scr[row * SCREEN_COLS..(row + 1) * SCREEN_COLS].copy_from_slice(src);
+		}
+
+		self.move_cursor();
+	}
+
+	unsafe fn clear(&mut self) {
+		self.erase(0, SCREEN_SIZE);
+
 		self.pos = 0;
+		self.view_offset = 0;
 	}
 
 	unsafe fn move_cursor(&mut self) {
+		// cursor placed outside of the screen is hidden while scrollback is shown
+		let pos = if self.view_offset > 0 { SCREEN_SIZE } else { cmp::min(self.pos, SCREEN_SIZE - 1) };
+
 		// requesting write for cursor position high byte
 		pio::out_byte(0xe, 0x3d4);
-		pio::out_byte((self.pos >> 8) as u32, 0x3d5);
+		pio::out_byte((pos >> 8) as u32, 0x3d5);
 
 		// requesting cursor position low byte write
 		pio::out_byte(0xf, 0x3d4);
-		pio::out_byte((self.pos & 0xff) as u32, 0x3d5);
+		pio::out_byte((pos & 0xff) as u32, 0x3d5);
 	}
 
+	// BIOS data area cursor position isn't updated by the writer
 	unsafe fn get_cursor_pos(&self) -> (usize, usize) {
-		let cursor_pos_ptr: *const u16 = VIDEO_PAGE_0_CURSOR_POS_ADDR as *const u16;
-
-		let cursor_col = *cursor_pos_ptr & 0xff;
-		let cursor_row = *cursor_pos_ptr >> 8;
+		// position past the last cell is kept until the next character is put
+		let pos = cmp::min(self.pos, SCREEN_SIZE - 1);
 
-		(cursor_row as usize, cursor_col as usize)
+		(pos / SCREEN_COLS, pos % SCREEN_COLS)
 	}
 }
 
@@ -86,54 +409,394 @@ impl fmt::Write for ScreenWriter {
 	}
 }
 
+pub unsafe fn init() {
+	let output = CONSOLE_OUTPUT.get();
+
+	let mut scr_writer = SCR_WRITER.lock();
+
+	if ustr::cmp(output, "serial") == 0 {
+		scr_writer.vga = false;
+		scr_writer.serial = true;
+	} else if ustr::cmp(output, "all") == 0 {
+		scr_writer.serial = true;
+	}
+
+	if scr_writer.serial {
+		serial::init();
+	}
+}
+
+// switches console to panic report output: highlighted and sent to all devices,
+// console lock can be held by panicked code
+pub unsafe fn enter_panic_mode() {
+	let scr_writer = SCR_WRITER.get_unlocked();
+
+	if !scr_writer.serial {
+		serial::init();
+		scr_writer.serial = true;
+	}
+
+	scr_writer.vga = true;
+	scr_writer.attr = PANIC_ATTR;
+	scr_writer.esc_state = EscState::None;
+
+	// report should be visible
+	scr_writer.set_view_offset(0);
+}
+
+// runs f with console writer, falls back to lock-free access as print() does
+fn with_writer<R>(f: impl FnOnce(&mut ScreenWriter) -> R) -> R {
+	match SCR_WRITER.try_lock() {
+		Some(mut scr_writer) => f(&mut *scr_writer),
+		_ => unsafe { f(SCR_WRITER.get_unlocked()) }
+	}
+}
+
 pub unsafe fn clear() {
-	SCR_WRITER.clear();
+	with_writer(|scr_writer| scr_writer.clear());
 }
 
+// colors of the following output (escape sequences can be used as well)
+pub fn set_color(fg: Color, bg: Color) {
+	SCR_WRITER.lock().attr = attr(fg, bg);
+}
+
+pub fn reset_color() {
+	SCR_WRITER.lock().attr = DEFAULT_ATTR;
+}
+
+// scrollback paging, output returns to the live screen
+pub fn page_up() {
+	unsafe {
+		let mut scr_writer = SCR_WRITER.lock();
+		let offset = scr_writer.view_offset + SCREEN_ROWS - 1;
+
+		scr_writer.set_view_offset(offset);
+	}
+}
+
+pub fn page_down() {
+	unsafe {
+		let mut scr_writer = SCR_WRITER.lock();
+		let offset = scr_writer.view_offset.saturating_sub(SCREEN_ROWS - 1);
+
+		scr_writer.set_view_offset(offset);
+	}
+}
+
+// the whole formatted output (i.e. console_println! line) is written under the lock
 pub fn print(args: fmt::Arguments) {
+	// lock holder can't be preempted, so console is found locked only by fault or NMI handler
+	// interrupting console output or by panicked console code, such reports are written without lock
+	match SCR_WRITER.try_lock() {
+		Some(mut scr_writer) => {
+			fmt::write(&mut *scr_writer, args).unwrap();
+		},
+		_ => emergency_print(args)
+	}
+}
+
+// lock-free output, writer state can be inconsistent
+pub fn emergency_print(args: fmt::Arguments) {
 	unsafe {
-		fmt::write(&mut SCR_WRITER, args).unwrap();
+		let _ = fmt::write(SCR_WRITER.get_unlocked(), args);
 	}
 }
 
 pub fn print_str(s: &str) {
-	unsafe {
-		SCR_WRITER.print(s);
+	with_writer(|scr_writer| unsafe { scr_writer.print(s) });
+}
+
+pub fn print_bytes(bs: &[u8]) {
+	with_writer(|scr_writer| unsafe {
+		for b in bs {
+			scr_writer.write_char(*b);
+		}
+	});
+}
+
+// waits for input byte without echo
+fn read_key() -> u8 {
+	loop {
+		match KBD_BUF.pop_front() {
+			Some(c) => return c,
+			_ => task::suspend()
+		}
 	}
 }
 
 pub fn read_char() -> u8 {
+	let chr = read_key();
+
+	// echoing pressed character on the console
+	with_writer(|scr_writer| unsafe { scr_writer.write_char(chr) });
+
+	chr
+}
+
+pub fn get_cursor_pos() -> (usize, usize) {
+	with_writer(|scr_writer| unsafe { scr_writer.get_cursor_pos() })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EditKey {
+	Up,
+	Down,
+	Left,
+	Right,
+	Home,
+	End,
+	Delete,
+	Unknown
+}
+
+// non-character keys are sent as VT100 sequences, i.e. ESC [ A or ESC [ 3 ~,
+// sequence bytes are buffered together, so ESC followed by nothing or by other byte is
+// a standalone ESC key, the byte following it is returned to 'unread'
+fn read_esc_seq(unread: &mut Option<u8>) -> EditKey {
+	match KBD_BUF.pop_front() {
+		Some(b'[') => {},
+		chr => {
+			*unread = chr;
+			return EditKey::Unknown
+		}
+	}
+
+	let mut n: usize = 0;
+
 	loop {
-		let chr = match KBD_BUF.pop_front() {
-			Some(c) => c,
-			_ => {
-				task::suspend();
+		let chr = read_key();
+
+		return match chr {
+			b'0'..=b'9' => {
+				n = n.saturating_mul(10).saturating_add((chr - b'0') as usize);
 				continue
+			},
+			b'A' => EditKey::Up,
+			b'B' => EditKey::Down,
+			b'C' => EditKey::Right,
+			b'D' => EditKey::Left,
+			b'H' => EditKey::Home,
+			b'F' => EditKey::End,
+			b'~' => match n {
+				1 | 7 => EditKey::Home,
+				4 | 8 => EditKey::End,
+				3 => EditKey::Delete,
+				_ => EditKey::Unknown
+			},
+			_ => EditKey::Unknown
+		}
+	}
+}
+
+// line being edited is shown starting from the cursor position at the moment read_line is called,
+// screen is updated relative to the cursor (lines wrapped on the screen are handled by VGA writer)
+struct LineEditor<'a> {
+	buf: &'a mut vec::Vec<u8>,
+	// cursor position in the line
+	cur: usize
+}
+
+impl LineEditor<'_> {
+	// moving cursor right reprints characters
+	fn move_to(&mut self, pos: usize) {
+		let mut scr_writer = SCR_WRITER.lock();
+
+		unsafe {
+			if pos < self.cur {
+				scr_writer.cursor_back(self.cur - pos);
+			} else {
+				for c in self.buf[self.cur..pos].iter() {
+					scr_writer.write_char(*c);
+				}
 			}
-		};
+		}
+
+		self.cur = pos;
+	}
+
+	// prints line tail starting from position 'from' (cursor should be there), erases the rest of
+	// the line shown before edit (old_len long) and moves cursor to 'cur'
+	fn redraw(&mut self, from: usize, old_len: usize, cur: usize) {
+		let len = self.buf.len();
+		let pad = old_len.saturating_sub(len);
+
+		let mut scr_writer = SCR_WRITER.lock();
 
 		unsafe {
-			// echoing pressed character on the console
-			SCR_WRITER.write_char(chr);
+			for c in self.buf[from..].iter() {
+				scr_writer.write_char(*c);
+			}
+
+			for _ in 0..pad {
+				scr_writer.write_char(b' ');
+			}
+
+			scr_writer.cursor_back(len + pad - cur);
+		}
+
+		self.cur = cur;
+	}
+
+	fn insert(&mut self, chr: u8) {
+		let from = self.cur;
+		let old_len = self.buf.len();
+
+		self.buf.insert(from, chr);
+
+		self.redraw(from, old_len, from + 1);
+	}
+
+	// removes characters range, cursor is placed at range start
+	fn remove(&mut self, start: usize, end: usize) {
+		if start >= end {
+			return
+		}
+
+		let old_len = self.buf.len();
+
+		self.move_to(start);
+
+		for _ in start..end {
+			self.buf.remove(start);
 		}
 
-		return chr
+		self.redraw(start, old_len, start);
+	}
+
+	fn replace(&mut self, line: &[u8]) {
+		let old_len = self.buf.len();
+
+		self.move_to(0);
+
+		self.buf.clear();
+		for c in line {
+			self.buf.push(*c);
+		}
+
+		let len = self.buf.len();
+		self.redraw(0, old_len, len);
+	}
+
+	// start of the word before cursor (Ctrl-W)
+	fn word_start(&self) -> usize {
+		let mut pos = self.cur;
+
+		while pos > 0 && self.buf[pos - 1] == b' ' {
+			pos -= 1;
+		}
+
+		while pos > 0 && self.buf[pos - 1] != b' ' {
+			pos -= 1;
+		}
+
+		pos
 	}
 }
 
-pub fn get_cursor_pos() -> (usize, usize) {
-	unsafe {
-		SCR_WRITER.get_cursor_pos()
+fn copy_line(line: &[u8]) -> vec::Vec<u8> {
+	let mut copy = vec::Vec::with_cap(line.len() + 1);
+
+	for c in line {
+		copy.push(*c);
 	}
+
+	copy
 }
 
+// reads line with editing (buf contents is shown and can be edited as well),
+// up and down keys navigate through previously entered lines
 pub fn read_line(buf: &mut vec::Vec<u8>) {
+	let hist_len = HISTORY.lock().len();
+	let mut hist_idx = hist_len;
+
+	// line being edited before history navigation started
+	let mut pending: vec::Vec<u8> = vec::Vec::new();
+
+	let len = buf.len();
+	let mut ed = LineEditor { buf, cur: 0 };
+
+	// key read after standalone ESC
+	let mut unread: Option<u8> = None;
+
+	ed.redraw(0, 0, len);
+
 	loop {
-		let chr = read_char();
-		if chr == b'\n' {
-			break;
+		let len = ed.buf.len();
+
+		let key = match unread.take() {
+			Some(k) => k,
+			_ => read_key()
+		};
+
+		match key {
+			b'\n' | b'\r' => break,
+			ESC => match read_esc_seq(&mut unread) {
+				EditKey::Left if ed.cur > 0 => ed.move_to(ed.cur - 1),
+				EditKey::Right if ed.cur < len => ed.move_to(ed.cur + 1),
+				EditKey::Home => ed.move_to(0),
+				EditKey::End => ed.move_to(len),
+				EditKey::Delete => ed.remove(ed.cur, ed.cur + 1),
+				EditKey::Up if hist_idx > 0 => {
+					if hist_idx == hist_len {
+						pending = copy_line(ed.buf);
+					}
+
+					hist_idx -= 1;
+
+					let history = HISTORY.lock();
+					ed.replace(&history[hist_idx]);
+				},
+				EditKey::Down if hist_idx < hist_len => {
+					hist_idx += 1;
+
+					if hist_idx == hist_len {
+						ed.replace(&pending);
+					} else {
+						let history = HISTORY.lock();
+						ed.replace(&history[hist_idx]);
+					}
+				},
+				_ => {}
+			},
+			CTRL_A => ed.move_to(0),
+			CTRL_E => ed.move_to(len),
+			CTRL_U => ed.remove(0, ed.cur),
+			CTRL_K => ed.remove(ed.cur, len),
+			CTRL_W => {
+				let start = ed.word_start();
+				ed.remove(start, ed.cur);
+			},
+			BACKSPACE | DEL if ed.cur > 0 => ed.remove(ed.cur - 1, ed.cur),
+			chr @ 0x20..=0x7e => ed.insert(chr),
+			_ => {}
 		}
+	}
+
+	ed.move_to(ed.buf.len());
+
+	unsafe {
+		SCR_WRITER.lock().write_char(b'\n');
+	}
+
+	add_to_history(ed.buf);
+}
 
-		buf.push(chr);
+// empty lines and repeated lines are not saved
+fn add_to_history(line: &[u8]) {
+	if line.is_empty() {
+		return
 	}
+
+	let mut history = HISTORY.lock();
+
+	if history.len() > 0 && history[history.len() - 1][..] == *line {
+		return
+	}
+
+	if history.len() == HISTORY_SIZE {
+		history.remove(0);
+	}
+
+	history.push(copy_line(line));
 }
@@ -0,0 +1,253 @@
+use core::mem;
+use core::ptr;
+
+use crate::vm;
+use crate::vm::PAGE_SIZE;
+use crate::frame;
+use crate::task;
+
+const EI_NIDENT: usize = 16;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+// 32 bit little endian objects
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+
+const ET_EXEC: u16 = 2;
+const EM_386: u16 = 3;
+
+const PT_LOAD: u32 = 1;
+
+// segment is writable
+const PF_W: u32 = 0x2;
+
+// user stack is located at the end of the user part of address space
+const USER_STACK_TOP: usize = vm::USER_SPACE_END;
+const USER_STACK_PAGES: usize = 4;
+
+// the same as ElfHeader in boot/loader.c
+#[repr(C)]
+struct ElfHeader {
+	e_ident: [u8; EI_NIDENT],
+	e_type: u16,
+	e_machine: u16,
+	e_version: u32,
+	e_entry: usize,
+	e_phoff: usize,
+	e_shoff: usize,
+	e_flags: u32,
+	e_hsize: u16,
+	e_phentsize: u16,
+	e_phnum: u16,
+	e_shentsize: u16,
+	e_shnum: u16,
+	e_shstrndx: u16
+}
+
+// program header structure
+#[repr(C)]
+struct ElfProgHeader {
+	p_type: u32,
+	p_offset: usize,
+	p_vaddr: usize,
+	p_paddr: usize,
+	p_filesz: usize,
+	p_memsz: usize,
+	p_flags: u32,
+	p_align: u32
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+	// not an i386 executable
+	BadFormat,
+	// segment is outside of image or user part of address space
+	BadSegment,
+	// arguments do not fit to the stack page
+	BadArgs,
+	NoMemory
+}
+
+// loads program image to a new address space and starts user task,
+// program entry point is called as a function with argc and argv arguments and null return address
+pub fn exec(image: &[u8], args: &[&str]) -> Result<usize, LoadError> {
+	let pdbr = vm::create_addr_space().ok_or(LoadError::NoMemory)?;
+
+	let entry = match load(image, pdbr) {
+		Ok(e) => e,
+		Err(e) => {
+			vm::destroy_addr_space(pdbr);
+			return Err(e)
+		}
+	};
+
+	let user_esp = match init_stack(pdbr, args) {
+		Ok(esp) => esp,
+		Err(e) => {
+			vm::destroy_addr_space(pdbr);
+			return Err(e)
+		}
+	};
+
+	Ok(task::create_user_with_stack(entry, pdbr, user_esp))
+}
+
+// maps loadable segments to the address space, returns entry point address
+pub fn load(image: &[u8], pdbr: u32) -> Result<usize, LoadError> {
+	if image.len() < mem::size_of::<ElfHeader>() {
+		return Err(LoadError::BadFormat)
+	}
+
+	let elf_hdr = unsafe {
+		ptr::read_unaligned(image.as_ptr() as *const ElfHeader)
+	};
+
+	if elf_hdr.e_ident[..4] != ELF_MAGIC || elf_hdr.e_ident[4] != ELFCLASS32 || elf_hdr.e_ident[5] != ELFDATA2LSB {
+		return Err(LoadError::BadFormat)
+	}
+
+	if elf_hdr.e_type != ET_EXEC || elf_hdr.e_machine != EM_386 || elf_hdr.e_phentsize as usize != mem::size_of::<ElfProgHeader>() {
+		return Err(LoadError::BadFormat)
+	}
+
+	let ph_end = elf_hdr.e_phoff.checked_add(elf_hdr.e_phnum as usize * mem::size_of::<ElfProgHeader>());
+	if ph_end.map_or(true, |end| end > image.len()) {
+		return Err(LoadError::BadFormat)
+	}
+
+	for i in 0..elf_hdr.e_phnum as usize {
+		let prog_hdr = unsafe {
+			let ph_ptr = image.as_ptr().add(elf_hdr.e_phoff + i * mem::size_of::<ElfProgHeader>());
+			ptr::read_unaligned(ph_ptr as *const ElfProgHeader)
+		};
+
+		if prog_hdr.p_type == PT_LOAD {
+			load_segment(image, &prog_hdr, pdbr)?;
+		}
+	}
+
+	if vm::translate(pdbr, elf_hdr.e_entry).is_none() {
+		return Err(LoadError::BadFormat)
+	}
+
+	Ok(elf_hdr.e_entry)
+}
+
+fn load_segment(image: &[u8], prog_hdr: &ElfProgHeader, pdbr: u32) -> Result<(), LoadError> {
+	let seg_start = prog_hdr.p_vaddr;
+	let seg_end = seg_start.checked_add(prog_hdr.p_memsz).ok_or(LoadError::BadSegment)?;
+
+	let offset_end = prog_hdr.p_offset.checked_add(prog_hdr.p_filesz).ok_or(LoadError::BadSegment)?;
+
+	if prog_hdr.p_filesz > prog_hdr.p_memsz || offset_end > image.len() {
+		return Err(LoadError::BadSegment)
+	}
+
+	if seg_start < vm::USER_SPACE_START || seg_end > vm::USER_SPACE_END {
+		return Err(LoadError::BadSegment)
+	}
+
+	// can't overflow since file size doesn't exceed memory size
+	let file_end = seg_start + prog_hdr.p_filesz;
+
+	let write = prog_hdr.p_flags & PF_W != 0;
+
+	let mut pg_addr = seg_start & !(PAGE_SIZE - 1);
+	while pg_addr < seg_end {
+		// page can be shared with the previous segment, it becomes writable if any of segments is writable
+		let frame = match vm::translate(pdbr, pg_addr) {
+			Some(f) => {
+				if write {
+					vm::map_page(pdbr, pg_addr, f, true);
+				}
+
+				f
+			},
+			_ => {
+				let f = frame::alloc().ok_or(LoadError::NoMemory)?;
+
+				if !vm::map_page(pdbr, pg_addr, f, write) {
+					frame::free(f);
+					return Err(LoadError::NoMemory)
+				}
+
+				f
+			}
+		};
+
+		// copying part of the segment file contents located in this page (the rest is zeroed)
+		let copy_start = if seg_start > pg_addr { seg_start } else { pg_addr };
+		let copy_end = if file_end < pg_addr + PAGE_SIZE { file_end } else { pg_addr + PAGE_SIZE };
+
+		if copy_start < copy_end {
+			let src_offset = prog_hdr.p_offset + (copy_start - seg_start);
+
+			unsafe {
+				// page frames are identity mapped
+				ptr::copy_nonoverlapping(image.as_ptr().add(src_offset), (frame + (copy_start - pg_addr)) as *mut u8, copy_end - copy_start);
+			}
+		}
+
+		pg_addr += PAGE_SIZE;
+	}
+
+	Ok(())
+}
+
+// maps user stack and places arguments to it's top page, returns initial stack pointer
+fn init_stack(pdbr: u32, args: &[&str]) -> Result<usize, LoadError> {
+	let mut top_frame = 0;
+
+	for i in 1..=USER_STACK_PAGES {
+		let frame = frame::alloc().ok_or(LoadError::NoMemory)?;
+
+		if !vm::map_page(pdbr, USER_STACK_TOP - i * PAGE_SIZE, frame, true) {
+			frame::free(frame);
+			return Err(LoadError::NoMemory)
+		}
+
+		if i == 1 {
+			top_frame = frame;
+		}
+	}
+
+	let top_page = USER_STACK_TOP - PAGE_SIZE;
+
+	// argument strings at the top, argv array, argv, argc and return address below them
+	let ptrs_size = (args.len() + 4) * mem::size_of::<u32>();
+	let strs_size: usize = args.iter().map(|a| a.len() + 1).sum();
+
+	if ptrs_size + strs_size + mem::size_of::<u32>() > PAGE_SIZE {
+		return Err(LoadError::BadArgs)
+	}
+
+	unsafe {
+		let mut pos = PAGE_SIZE - strs_size;
+		let argv_pos = (pos & !(mem::size_of::<u32>() - 1)) - (args.len() + 1) * mem::size_of::<u32>();
+
+		let argv = (top_frame + argv_pos) as *mut u32;
+
+		for (i, arg) in args.iter().enumerate() {
+			let arg_ptr = (top_frame + pos) as *mut u8;
+
+			ptr::copy_nonoverlapping(arg.as_ptr(), arg_ptr, arg.len());
+			*arg_ptr.add(arg.len()) = 0;
+
+			*argv.add(i) = (top_page + pos) as u32;
+
+			pos += arg.len() + 1;
+		}
+
+		*argv.add(args.len()) = 0;
+
+		let esp_pos = argv_pos - 3 * mem::size_of::<u32>();
+
+		let sp = (top_frame + esp_pos) as *mut u32;
+		*sp = 0;
+		*sp.add(1) = args.len() as u32;
+		*sp.add(2) = (top_page + argv_pos) as u32;
+
+		Ok(top_page + esp_pos)
+	}
+}
@@ -0,0 +1,133 @@
+use crate::task;
+use crate::gdt;
+use crate::intr;
+use crate::intr::InterruptFrame;
+use crate::backtrace;
+
+#[link(name = "uos")]
+extern {
+	fn get_cr0() -> u32;
+
+	fn get_cr2() -> u32;
+
+	fn get_cr3() -> u32;
+
+	fn get_cr4() -> u32;
+}
+
+pub const EXCEPTIONS_CNT: usize = 32;
+
+const EXCEPTION_NAMES: [&str; EXCEPTIONS_CNT] = [
+	"divide error",
+	"debug",
+	"non-maskable interrupt",
+	"breakpoint",
+	"overflow",
+	"bound range exceeded",
+	"invalid opcode",
+	"device not available",
+	"double fault",
+	"coprocessor segment overrun",
+	"invalid TSS",
+	"segment not present",
+	"stack-segment fault",
+	"general protection fault",
+	"page fault",
+	"reserved",
+	"x87 floating point error",
+	"alignment check",
+	"machine check",
+	"SIMD floating point error",
+	"virtualization exception",
+	"control protection exception",
+	"reserved",
+	"reserved",
+	"reserved",
+	"reserved",
+	"reserved",
+	"reserved",
+	"hypervisor injection exception",
+	"VMM communication exception",
+	"security exception",
+	"reserved"
+];
+
+// debug, NMI, breakpoint and overflow: execution continues after report
+const NON_FATAL_EXCEPTIONS: [u32; 4] = [1, 2, 3, 4];
+
+// the first task (system shell) can't be killed
+const INIT_TASK_ID: usize = 0;
+
+pub fn name(vec_num: usize) -> &'static str {
+	EXCEPTION_NAMES[vec_num & (EXCEPTIONS_CNT - 1)]
+}
+
+pub unsafe fn dispatch(frame: &InterruptFrame) {
+	intr::count(frame.vec_num as usize);
+
+	// task queue can be locked by the faulting code
+	let tid = task::try_curr_task_id();
+
+	print_report(frame, tid);
+
+	if NON_FATAL_EXCEPTIONS.contains(&frame.vec_num) {
+		return
+	}
+
+	// faulting kernel code can hold locks, so only user mode tasks are killed
+	match tid {
+		Some(t) if frame.is_user_mode() && t != INIT_TASK_ID => {
+			console_println!("task {} killed", t);
+
+			task::exit();
+		},
+		_ => {
+			console_println!("system halted");
+
+			intr::halt();
+		}
+	}
+}
+
+unsafe fn print_report(frame: &InterruptFrame, tid: Option<usize>) {
+	let name = name(frame.vec_num as usize);
+	let mode = if frame.is_user_mode() { "user" } else { "kernel" };
+
+	match tid {
+		Some(t) => console_println!("*** {} ({}) error code: {:#x} in {} mode, task {}", name, frame.vec_num, frame.err_code, mode, t),
+		_ => console_println!("*** {} ({}) error code: {:#x} in {} mode, task unknown", name, frame.vec_num, frame.err_code, mode)
+	}
+
+	console_println!("eax: {:08x} ebx: {:08x} ecx: {:08x} edx: {:08x}", frame.eax, frame.ebx, frame.ecx, frame.edx);
+	console_println!("esi: {:08x} edi: {:08x} ebp: {:08x} esp: {:08x}", frame.esi, frame.edi, frame.ebp, frame.stack_ptr());
+	console_println!("eip: {:08x} cs: {:04x} ds: {:04x} eflags: {:08x}", frame.eip, frame.cs, frame.ds, frame.eflags);
+	console_println!("cr0: {:08x} cr2: {:08x} cr3: {:08x} cr4: {:08x}", get_cr0(), get_cr2(), get_cr3(), get_cr4());
+
+	// user mode code is not covered by kernel symbol table
+	if !frame.is_user_mode() {
+		backtrace::print(frame.eip as usize, frame.ebp as usize);
+	}
+}
+
+// runs as a separate task, interrupted task state is saved to the main TSS
+#[no_mangle]
+pub unsafe extern fn double_fault_handler(err_code: u32) {
+	let state = gdt::interrupted_state();
+
+	match task::try_curr_task_id() {
+		Some(t) => console_println!("*** double fault error code: {:#x}, task {}", err_code, t),
+		_ => console_println!("*** double fault error code: {:#x}, task unknown", err_code)
+	}
+
+	console_println!("eax: {:08x} ebx: {:08x} ecx: {:08x} edx: {:08x}", state.eax, state.ebx, state.ecx, state.edx);
+	console_println!("esi: {:08x} edi: {:08x} ebp: {:08x} esp: {:08x}", state.esi, state.edi, state.ebp, state.esp);
+	console_println!("eip: {:08x} cs: {:04x} ss: {:04x} eflags: {:08x}", state.eip, state.cs, state.ss, state.eflags);
+	// page directory base is not saved on task switch
+	console_println!("cr2: {:08x}", get_cr2());
+
+	if state.cs & 0x3 == 0 {
+		backtrace::print(state.eip as usize, state.ebp as usize);
+	}
+
+	console_println!("system halted");
+}
@@ -0,0 +1,127 @@
+use core::ptr;
+use core::cmp;
+
+use crate::lock;
+use crate::boot;
+use crate::vm;
+use crate::vm::PAGE_SIZE;
+
+#[link(name = "uos")]
+extern {
+	// system image boundaries (defined by linker script)
+	static __kernel_start: u8;
+
+	static __kernel_end: u8;
+}
+
+// upper memory starts at 1mb
+const UPPER_MEM_START: usize = 0x100000;
+
+// page frames are taken from the available memory above system image and boot modules,
+// only identity mapped first 4mb can be managed
+const FRAMES_LIMIT: usize = 0x400000;
+
+const FRAMES_CNT: usize = FRAMES_LIMIT / PAGE_SIZE;
+
+// allocation bitmap, set bit means frame is in use (or not available)
+static FRAME_MAP: lock::Mutex<[u32; FRAMES_CNT / 32]> = lock::Mutex::new([!0; FRAMES_CNT / 32]);
+
+pub unsafe fn init() {
+	// system image and boot modules occupied frames are never allocated
+	let kernel_start = &__kernel_start as *const u8 as usize;
+	let kernel_end = &__kernel_end as *const u8 as usize;
+
+	let mut reserved_end = kernel_end;
+
+	// stage 2 loader maps system image pages to the location of ELF file sections,
+	// so physical pages of the image can be located above it's virtual end
+	let mut addr = kernel_start;
+	while addr < kernel_end {
+		if let Some(phys_addr) = vm::kernel_translate(addr) {
+			reserved_end = cmp::max(reserved_end, phys_addr + PAGE_SIZE);
+		}
+
+		addr += PAGE_SIZE;
+	}
+
+	for m in boot::info().modules() {
+		if m.end > reserved_end {
+			reserved_end = m.end;
+		}
+	}
+
+	let mem_regions = boot::info().mem_regions();
+
+	if mem_regions.is_empty() {
+		// no memory map provided by loader, upper memory size is used instead
+		let upper_mem_end = UPPER_MEM_START + boot::info().mem_upper * 1024;
+
+		release(reserved_end, cmp::min(upper_mem_end, FRAMES_LIMIT));
+	}
+
+	for r in mem_regions.iter().filter(|r| r.kind == boot::MEM_REGION_AVAILABLE) {
+		// regions can be located above 4gb
+		let start = cmp::max(r.base, reserved_end as u64);
+		let end = cmp::min(r.base.saturating_add(r.len), FRAMES_LIMIT as u64);
+
+		if start < end {
+			release(start as usize, end as usize);
+		}
+	}
+}
+
+// marks whole frames of the area as free
+fn release(start: usize, end: usize) {
+	let mut frame_map = FRAME_MAP.lock();
+
+	let mut addr = (start + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+	while addr + PAGE_SIZE <= end {
+		let idx = addr / PAGE_SIZE;
+		frame_map[idx / 32] &= !(1 << (idx % 32));
+
+		addr += PAGE_SIZE;
+	}
+}
+
+// returns physical address of zeroed page frame
+pub fn alloc() -> Option<usize> {
+	let mut frame_map = FRAME_MAP.lock();
+
+	for i in 0..frame_map.len() {
+		if frame_map[i] == !0 {
+			continue
+		}
+
+		let bit = (!frame_map[i]).trailing_zeros() as usize;
+		frame_map[i] |= 1 << bit;
+
+		let frame = (i * 32 + bit) * PAGE_SIZE;
+
+		unsafe {
+			ptr::write_bytes(frame as *mut u8, 0, PAGE_SIZE);
+		}
+
+		return Some(frame)
+	}
+
+	None
+}
+
+pub fn free(frame: usize) {
+	if frame >= FRAMES_LIMIT {
+		console_println!("attempt to free page frame outside of managed area: {:#x}", frame);
+
+		return
+	}
+
+	let idx = frame / PAGE_SIZE;
+
+	let mut frame_map = FRAME_MAP.lock();
+	frame_map[idx / 32] &= !(1 << (idx % 32));
+}
+
+pub fn free_cnt() -> usize {
+	let frame_map = FRAME_MAP.lock();
+
+	frame_map.iter().map(|w| w.count_zeros() as usize).sum()
+}
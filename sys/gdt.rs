@@ -0,0 +1,164 @@
+use core::mem;
+use core::ptr;
+
+#[link(name = "uos")]
+extern {
+	fn load_gdt(gdt_info: *const GdtInfo);
+
+	fn load_tr(tss_sel: u32);
+
+	fn get_cr3() -> u32;
+
+	// double fault task entry point
+	fn double_fault_task();
+}
+
+// kernel selectors are the same as in the loader GDT
+pub const KERNEL_CODE_SEL: u16 = 0x8;
+pub const KERNEL_DATA_SEL: u16 = 0x10;
+
+pub const USER_CODE_SEL: u16 = 0x18 | 0x3;
+pub const USER_DATA_SEL: u16 = 0x20 | 0x3;
+
+pub const TSS_SEL: u16 = 0x28;
+
+// double fault handler runs as a separate task
+pub const DOUBLE_FAULT_TSS_SEL: u16 = 0x30;
+
+const GDT_ENTRIES: usize = 7;
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 0x1000;
+
+// present, DPL 0, code execute/read and data read/write access rights
+const KERNEL_CODE_ACCESS: u8 = 0x9a;
+const KERNEL_DATA_ACCESS: u8 = 0x92;
+
+// the same with DPL 3
+const USER_CODE_ACCESS: u8 = 0xfa;
+const USER_DATA_ACCESS: u8 = 0xf2;
+
+// present, DPL 0, available 32 bit TSS
+const TSS_ACCESS: u8 = 0x89;
+
+// 4k granularity and 32 bit operand size
+const SEG_FLAGS: u8 = 0xc;
+
+#[repr(C, packed)]
+struct GdtInfo {
+	limit: u16,
+	base: u32
+}
+
+// 32 bit task state segment
+#[repr(C)]
+pub struct Tss {
+	pub link: u32,
+	pub esp0: u32,
+	pub ss0: u32,
+	pub esp1: u32,
+	pub ss1: u32,
+	pub esp2: u32,
+	pub ss2: u32,
+	pub cr3: u32,
+	pub eip: u32,
+	pub eflags: u32,
+	pub eax: u32,
+	pub ecx: u32,
+	pub edx: u32,
+	pub ebx: u32,
+	pub esp: u32,
+	pub ebp: u32,
+	pub esi: u32,
+	pub edi: u32,
+	pub es: u32,
+	pub cs: u32,
+	pub ss: u32,
+	pub ds: u32,
+	pub fs: u32,
+	pub gs: u32,
+	pub ldt: u32,
+	pub trap: u16,
+	pub iomap_base: u16
+}
+
+const NULL_TSS: Tss = Tss { link: 0, esp0: 0, ss0: 0, esp1: 0, ss1: 0, esp2: 0, ss2: 0, cr3: 0, eip: 0, eflags: 0, eax: 0, ecx: 0, edx: 0, ebx: 0,
+	esp: 0, ebp: 0, esi: 0, edi: 0, es: 0, cs: 0, ss: 0, ds: 0, fs: 0, gs: 0, ldt: 0, trap: 0, iomap_base: 0 };
+
+static mut GDT: [u64; GDT_ENTRIES] = [0; GDT_ENTRIES];
+
+static mut TSS: Tss = NULL_TSS;
+
+static mut DOUBLE_FAULT_TSS: Tss = NULL_TSS;
+
+// known good stack used when kernel stack is broken
+static mut DOUBLE_FAULT_STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+
+fn descriptor(base: u32, limit: u32, access: u8, flags: u8) -> u64 {
+	let mut desc: u64 = (limit & 0xffff) as u64;
+
+	desc |= ((base & 0xffffff) as u64) << 16;
+	desc |= (access as u64) << 40;
+	desc |= (((limit >> 16) & 0xf) as u64) << 48;
+	desc |= ((flags & 0xf) as u64) << 52;
+	desc |= ((base >> 24) as u64) << 56;
+
+	desc
+}
+
+pub unsafe fn init() {
+	GDT[1] = descriptor(0, 0xfffff, KERNEL_CODE_ACCESS, SEG_FLAGS);
+	GDT[2] = descriptor(0, 0xfffff, KERNEL_DATA_ACCESS, SEG_FLAGS);
+	GDT[3] = descriptor(0, 0xfffff, USER_CODE_ACCESS, SEG_FLAGS);
+	GDT[4] = descriptor(0, 0xfffff, USER_DATA_ACCESS, SEG_FLAGS);
+
+	// stack used on privilege level change, esp0 is updated on every task switch
+	TSS.ss0 = KERNEL_DATA_SEL as u32;
+	// I/O permission bitmap is absent
+	TSS.iomap_base = mem::size_of::<Tss>() as u16;
+
+	GDT[5] = descriptor(ptr::addr_of!(TSS) as u32, (mem::size_of::<Tss>() - 1) as u32, TSS_ACCESS, 0);
+
+	init_double_fault_tss();
+	GDT[6] = descriptor(ptr::addr_of!(DOUBLE_FAULT_TSS) as u32, (mem::size_of::<Tss>() - 1) as u32, TSS_ACCESS, 0);
+
+	let gdt_info = GdtInfo {
+		limit: (mem::size_of::<[u64; GDT_ENTRIES]>() - 1) as u16,
+		base: ptr::addr_of!(GDT) as u32
+	};
+
+	load_gdt(&gdt_info);
+
+	load_tr(TSS_SEL as u32);
+}
+
+// double fault task runs in kernel address space with interrupts disabled
+unsafe fn init_double_fault_tss() {
+	let df_tss = &mut *ptr::addr_of_mut!(DOUBLE_FAULT_TSS);
+
+	df_tss.cr3 = get_cr3();
+	df_tss.eip = double_fault_task as *const () as u32;
+	df_tss.eflags = 0x2;
+	df_tss.esp = ptr::addr_of!(DOUBLE_FAULT_STACK) as u32 + (DOUBLE_FAULT_STACK_SIZE - 4) as u32;
+
+	df_tss.cs = KERNEL_CODE_SEL as u32;
+	df_tss.ss = KERNEL_DATA_SEL as u32;
+	df_tss.ds = KERNEL_DATA_SEL as u32;
+	df_tss.es = KERNEL_DATA_SEL as u32;
+	df_tss.fs = KERNEL_DATA_SEL as u32;
+	df_tss.gs = KERNEL_DATA_SEL as u32;
+
+	df_tss.iomap_base = mem::size_of::<Tss>() as u16;
+}
+
+// state of the task interrupted by double fault is saved to the main TSS
+pub fn interrupted_state() -> &'static Tss {
+	unsafe {
+		&*ptr::addr_of!(TSS)
+	}
+}
+
+pub fn set_kernel_stack(esp0: usize) {
+	unsafe {
+		TSS.esp0 = esp0 as u32;
+	}
+}
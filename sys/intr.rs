@@ -1,14 +1,393 @@
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+
 use crate::pio;
+use crate::vec;
+use crate::alloc;
+use crate::task;
+use crate::apic;
+use crate::param;
+use crate::gdt;
+use crate::syscall;
+use crate::exception;
 
 #[link(name = "uos")]
 extern {
-	pub fn register_handler(vec_num: usize, handler: extern fn());
+	fn load_idt(idt_info: *const IdtInfo);
 
-	pub fn register_handler_with_err_code(vec_num: usize, handler: extern fn(err_code: usize));
+	pub fn intr_enable();
 
-	pub fn load_idt();
+	pub fn intr_disable();
 
-	pub fn intr_enable();
+	pub fn halt() -> !;
+
+	pub fn wait_intr();
+
+	fn get_eflags() -> u32;
+
+	// incremented by local APIC spurious interrupt handler
+	static apic_spurious_count: u32;
+
+	// entry stubs pushing uniform frame and calling isr_dispatch
+	static isr_stubs: [u32; VEC_CNT];
+
+	// entry points with their own frame layout
+	fn switch_task();
+
+	fn apic_spurious();
+
+	fn syscall_entry();
+}
+
+const EFLAGS_IF_BIT: u32 = 0x200;
+
+// hardware interrupts vectors
+pub const IRQ_BASE_VEC_NUM: usize = 32;
+pub const IRQ_CNT: usize = 16;
+
+// registers saved by interrupt entry code, vector number, error code (0 for hardware interrupts)
+// and interrupt frame, user_esp and user_ss are present only if interrupted code ran in user mode
+#[repr(C)]
+pub struct InterruptFrame {
+	pub ds: u32,
+	pub edi: u32,
+	pub esi: u32,
+	pub ebp: u32,
+	pub esp: u32,
+	pub ebx: u32,
+	pub edx: u32,
+	pub ecx: u32,
+	pub eax: u32,
+	pub vec_num: u32,
+	pub err_code: u32,
+	pub eip: u32,
+	pub cs: u32,
+	pub eflags: u32,
+	pub user_esp: u32,
+	pub user_ss: u32
+}
+
+impl InterruptFrame {
+	pub fn is_user_mode(&self) -> bool {
+		self.cs & 0x3 != 0
+	}
+
+	// stack pointer at the moment of interrupt
+	pub fn stack_ptr(&self) -> u32 {
+		if self.is_user_mode() {
+			self.user_esp
+		} else {
+			// interrupt frame without privilege level change contains eip, cs and eflags only
+			&self.user_esp as *const u32 as u32
+		}
+	}
+}
+
+pub const VEC_CNT: usize = 256;
+
+pub const KERNEL_DPL: u8 = 0;
+pub const USER_DPL: u8 = 3;
+
+// present bit and gate types in the descriptor flags word
+const GATE_PRESENT: u16 = 0x8000;
+const INTR_GATE_TYPE: u16 = 0xe00;
+const TRAP_GATE_TYPE: u16 = 0xf00;
+const TASK_GATE_TYPE: u16 = 0x500;
+
+#[repr(C, packed)]
+struct IdtInfo {
+	limit: u16,
+	base: u32
+}
+
+// interrupt, trap and task gates share the same layout (offset is not used by task gate)
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct IdtEntry {
+	offset_lo: u16,
+	selector: u16,
+	flags: u16,
+	offset_hi: u16
+}
+
+const NULL_IDT_ENTRY: IdtEntry = IdtEntry { offset_lo: 0, selector: 0, flags: 0, offset_hi: 0 };
+
+#[repr(C)]
+pub struct Idt {
+	entries: [IdtEntry; VEC_CNT]
+}
+
+impl Idt {
+	pub const fn new() -> Idt {
+		Idt {
+			entries: [NULL_IDT_ENTRY; VEC_CNT]
+		}
+	}
+
+	// interrupts are disabled on handler entry
+	pub fn set_interrupt_gate(&mut self, vec_num: usize, handler: usize, dpl: u8) {
+		self.set_gate(vec_num, handler as u32, gdt::KERNEL_CODE_SEL, INTR_GATE_TYPE, dpl);
+	}
+
+	// interrupts state is not changed on handler entry
+	pub fn set_trap_gate(&mut self, vec_num: usize, handler: usize, dpl: u8) {
+		self.set_gate(vec_num, handler as u32, gdt::KERNEL_CODE_SEL, TRAP_GATE_TYPE, dpl);
+	}
+
+	// handler runs as a separate task described by TSS
+	pub fn set_task_gate(&mut self, vec_num: usize, tss_sel: u16, dpl: u8) {
+		self.set_gate(vec_num, 0, tss_sel, TASK_GATE_TYPE, dpl);
+	}
+
+	// lowest privilege level allowed to raise the vector using int instruction
+	pub fn set_dpl(&mut self, vec_num: usize, dpl: u8) {
+		let e = &mut self.entries[vec_num];
+
+		e.flags = (e.flags & !(0x3 << 13)) | ((dpl as u16 & 0x3) << 13);
+	}
+
+	pub fn clear_gate(&mut self, vec_num: usize) {
+		self.entries[vec_num] = NULL_IDT_ENTRY;
+	}
+
+	pub fn is_present(&self, vec_num: usize) -> bool {
+		self.entries[vec_num].flags & GATE_PRESENT != 0
+	}
+
+	// table should stay in place while loaded
+	pub unsafe fn load(&'static self) {
+		let idt_info = IdtInfo {
+			limit: (mem::size_of::<[IdtEntry; VEC_CNT]>() - 1) as u16,
+			base: &self.entries as *const [IdtEntry; VEC_CNT] as u32
+		};
+
+		load_idt(&idt_info);
+	}
+
+	fn set_gate(&mut self, vec_num: usize, offset: u32, selector: u16, gate_type: u16, dpl: u8) {
+		self.entries[vec_num] = IdtEntry {
+			offset_lo: offset as u16,
+			selector,
+			flags: GATE_PRESENT | gate_type | ((dpl as u16 & 0x3) << 13),
+			offset_hi: (offset >> 16) as u16
+		};
+	}
+}
+
+static mut IDT: Idt = Idt::new();
+
+// per vector interrupts counters
+const ZERO_CNT: AtomicUsize = AtomicUsize::new(0);
+static INTR_CNT: [AtomicUsize; VEC_CNT] = [ZERO_CNT; VEC_CNT];
+
+// legacy PIC spurious interrupts and interrupts without handlers
+static SPURIOUS_CNT: AtomicUsize = AtomicUsize::new(0);
+static UNHANDLED_CNT: AtomicUsize = AtomicUsize::new(0);
+
+// should be called by every interrupt dispatcher
+pub fn count(vec_num: usize) {
+	if vec_num < VEC_CNT {
+		INTR_CNT[vec_num].fetch_add(1, Ordering::Relaxed);
+	}
+}
+
+pub fn intr_cnt(vec_num: usize) -> usize {
+	if vec_num < VEC_CNT {
+		INTR_CNT[vec_num].load(Ordering::Relaxed)
+	} else {
+		0
+	}
+}
+
+pub fn spurious_cnt() -> usize {
+	let apic_cnt = unsafe {
+		ptr::read_volatile(&apic_spurious_count) as usize
+	};
+
+	SPURIOUS_CNT.load(Ordering::Relaxed) + apic_cnt
+}
+
+pub fn unhandled_cnt() -> usize {
+	UNHANDLED_CNT.load(Ordering::Relaxed)
+}
+
+pub fn handlers_cnt(irq: usize) -> usize {
+	if irq >= IRQ_CNT {
+		return 0
+	}
+
+	unsafe {
+		IRQ_HANDLERS[irq].len()
+	}
+}
+
+// handler closure placed to the kernel heap
+struct Handler {
+	id: usize,
+	func: *mut dyn FnMut(&mut InterruptFrame)
+}
+
+const NO_HANDLERS: vec::Vec<Handler> = vec::Vec::new();
+
+// handlers lists are modified with interrupts disabled only
+static mut IRQ_HANDLERS: [vec::Vec<Handler>; IRQ_CNT] = [NO_HANDLERS; IRQ_CNT];
+
+static mut NEXT_HANDLER_ID: usize = 1;
+
+// handlers lists can't be changed while they are iterated, i.e. by handler itself
+static DISPATCHING: AtomicBool = AtomicBool::new(false);
+
+// adds handler to the interrupt line handlers list (line can be shared by several devices),
+// returns handler id which can be used to unregister it (handlers can't register new ones)
+pub fn register<F: FnMut(&mut InterruptFrame) + 'static>(vec_num: usize, handler: F) -> Option<usize> {
+	if vec_num < IRQ_BASE_VEC_NUM || vec_num >= IRQ_BASE_VEC_NUM + IRQ_CNT {
+		return None
+	}
+
+	if DISPATCHING.load(Ordering::SeqCst) {
+		return None
+	}
+
+	// kernel heap blocks are aligned to the double word boundary
+	if mem::align_of::<F>() > 2 * mem::size_of::<usize>() {
+		return None
+	}
+
+	let size = if mem::size_of::<F>() > 0 { mem::size_of::<F>() } else { 1 };
+
+	let func_ptr = alloc::alloc(size) as *mut F;
+	if func_ptr.is_null() {
+		return None
+	}
+
+	unsafe {
+		ptr::write(func_ptr, handler);
+
+		let intr_enabled = save_and_disable();
+
+		let id = NEXT_HANDLER_ID;
+		NEXT_HANDLER_ID += 1;
+
+		IRQ_HANDLERS[vec_num - IRQ_BASE_VEC_NUM].push(Handler { id, func: func_ptr });
+
+		// line is claimed by the first handler
+		unmask_irq(vec_num - IRQ_BASE_VEC_NUM);
+
+		restore(intr_enabled);
+
+		Some(id)
+	}
+}
+
+// handler can't unregister itself (or other handlers) when called
+pub fn unregister(vec_num: usize, id: usize) -> bool {
+	if vec_num < IRQ_BASE_VEC_NUM || vec_num >= IRQ_BASE_VEC_NUM + IRQ_CNT {
+		return false
+	}
+
+	if DISPATCHING.load(Ordering::SeqCst) {
+		return false
+	}
+
+	unsafe {
+		let intr_enabled = save_and_disable();
+
+		let handlers = &mut IRQ_HANDLERS[vec_num - IRQ_BASE_VEC_NUM];
+
+		let removed = match handlers.iter().position(|h| h.id == id) {
+			Some(pos) => handlers.remove(pos),
+			_ => None
+		};
+
+		if handlers.len() == 0 {
+			mask_irq(vec_num - IRQ_BASE_VEC_NUM);
+		}
+
+		restore(intr_enabled);
+
+		match removed {
+			Some(h) => {
+				ptr::drop_in_place(h.func);
+				alloc::dealloc(h.func as *mut u8);
+
+				true
+			},
+			_ => false
+		}
+	}
+}
+
+// disables interrupts, returns previous interrupts state
+pub fn save_and_disable() -> bool {
+	unsafe {
+		let enabled = get_eflags() & EFLAGS_IF_BIT != 0;
+
+		intr_disable();
+
+		enabled
+	}
+}
+
+pub fn restore(enabled: bool) {
+	if enabled {
+		unsafe {
+			intr_enable();
+		}
+	}
+}
+
+// called by common interrupt entry code for every vector without dedicated entry point
+#[no_mangle]
+pub unsafe extern fn isr_dispatch(frame: *mut InterruptFrame) {
+	let frame = &mut *frame;
+	let vec_num = frame.vec_num as usize;
+
+	if vec_num < exception::EXCEPTIONS_CNT {
+		exception::dispatch(frame);
+	} else if vec_num < IRQ_BASE_VEC_NUM + IRQ_CNT {
+		irq_dispatch(frame);
+	} else {
+		// software interrupt or interrupt from unknown source
+		count(vec_num);
+
+		UNHANDLED_CNT.fetch_add(1, Ordering::Relaxed);
+	}
+}
+
+// called with interrupts disabled
+unsafe fn irq_dispatch(frame: &mut InterruptFrame) {
+	let irq = frame.vec_num as usize - IRQ_BASE_VEC_NUM;
+
+	if !APIC_MODE && is_spurious(irq) {
+		SPURIOUS_CNT.fetch_add(1, Ordering::Relaxed);
+
+		// master controller is not aware that slave interrupt is spurious
+		if irq == SPURIOUS_SLAVE_IRQ {
+			pio::out_byte(OCW2_EOI, MASTER_ICW1_IOPORT_NUM);
+		}
+
+		return
+	}
+
+	count(frame.vec_num as usize);
+
+	if IRQ_HANDLERS[irq].len() == 0 {
+		UNHANDLED_CNT.fetch_add(1, Ordering::Relaxed);
+	}
+
+	DISPATCHING.store(true, Ordering::SeqCst);
+
+	for h in IRQ_HANDLERS[irq].iter() {
+		(*h.func)(frame);
+	}
+
+	DISPATCHING.store(false, Ordering::SeqCst);
+
+	eoi(irq);
+
+	// interrupt could make another task runnable or current task time slice could expire
+	task::preempt();
 }
 
 const MASTER_ICW1_IOPORT_NUM: u32 = 0x20;
@@ -17,7 +396,77 @@ const SLAVE_ICW1_IOPORT_NUM: u32 = 0xa0;
 const MASTER_ICW2_IOPORT_NUM: u32 = MASTER_ICW1_IOPORT_NUM + 1;
 const SLAVE_ICW2_IOPORT_NUM: u32 = SLAVE_ICW1_IOPORT_NUM + 1;
 
-pub unsafe fn init_pic() {
+// non-specific EOI and in-service register read commands
+const OCW2_EOI: u32 = 0x20;
+const OCW3_READ_ISR: u32 = 0x0b;
+
+// lines per controller
+const PIC_IRQ_CNT: usize = 8;
+
+// slave controller is connected to master IRQ2
+const CASCADE_IRQ: usize = 2;
+
+const SPURIOUS_MASTER_IRQ: usize = 7;
+const SPURIOUS_SLAVE_IRQ: usize = 15;
+
+// masked lines (set bit), all lines except cascade one are masked until handler is registered
+static mut IRQ_MASK: u16 = !(1 << CASCADE_IRQ);
+
+// legacy PICs are used if APIC is absent or disabled
+static NO_APIC: param::Param<bool> = param!("noapic", bool, false);
+
+static mut APIC_MODE: bool = false;
+
+pub unsafe fn init() {
+	init_pic();
+
+	if !NO_APIC.get() && apic::is_present() && apic::init(IRQ_BASE_VEC_NUM) {
+		// legacy PICs stay remapped (to avoid exceptions on spurious interrupts) but all lines are masked
+		pio::out_byte(0xff, MASTER_ICW2_IOPORT_NUM);
+		pio::out_byte(0xff, SLAVE_ICW2_IOPORT_NUM);
+
+		APIC_MODE = true;
+
+		// routing lines claimed before initialization
+		write_irq_mask();
+	}
+
+	init_idt();
+
+	(*ptr::addr_of!(IDT)).load();
+
+	intr_enable();
+}
+
+// every vector gets either generic stub or dedicated entry point
+unsafe fn init_idt() {
+	let idt = &mut *ptr::addr_of_mut!(IDT);
+
+	for vec_num in 0..VEC_CNT {
+		idt.set_interrupt_gate(vec_num, isr_stubs[vec_num] as usize, KERNEL_DPL);
+	}
+
+	// breakpoint and overflow traps
+	idt.set_trap_gate(3, isr_stubs[3] as usize, KERNEL_DPL);
+	idt.set_trap_gate(4, isr_stubs[4] as usize, KERNEL_DPL);
+
+	// double fault is handled by separate task (current stack could be broken)
+	idt.set_task_gate(8, gdt::DOUBLE_FAULT_TSS_SEL, KERNEL_DPL);
+
+	idt.set_interrupt_gate(task::SWITCH_TASK_VEC_NUM, switch_task as *const () as usize, KERNEL_DPL);
+	idt.set_interrupt_gate(apic::SPURIOUS_VEC_NUM, apic_spurious as *const () as usize, KERNEL_DPL);
+
+	// system calls are accessible from user space
+	idt.set_interrupt_gate(syscall::SYSCALL_VEC_NUM, syscall_entry as *const () as usize, USER_DPL);
+}
+
+pub fn is_apic_mode() -> bool {
+	unsafe {
+		APIC_MODE
+	}
+}
+
+unsafe fn init_pic() {
 	// ICW1 edge triggered mode
 	pio::out_byte(0x11, MASTER_ICW1_IOPORT_NUM);
 	pio::out_byte(0x11, SLAVE_ICW1_IOPORT_NUM);
@@ -35,16 +484,80 @@ pub unsafe fn init_pic() {
 	pio::out_byte(0x1, MASTER_ICW2_IOPORT_NUM);
 	pio::out_byte(0x1, SLAVE_ICW2_IOPORT_NUM );
 
-	// OCW1 unmasking all interrupts
-	pio::out_byte(0, MASTER_ICW2_IOPORT_NUM);
-	pio::out_byte(0, SLAVE_ICW2_IOPORT_NUM);
+	// OCW1 unmasking only lines claimed by drivers
+	write_irq_mask();
+}
+
+// slave controller is involved only for IRQ 8-15
+pub unsafe fn eoi(irq: usize) {
+	if APIC_MODE {
+		apic::eoi();
 
-	load_idt();
+		return
+	}
 
-	intr_enable();
+	if irq >= PIC_IRQ_CNT {
+		pio::out_byte(OCW2_EOI, SLAVE_ICW1_IOPORT_NUM);
+	}
+
+	pio::out_byte(OCW2_EOI, MASTER_ICW1_IOPORT_NUM);
+}
+
+pub fn mask_irq(irq: usize) {
+	if irq >= IRQ_CNT {
+		return
+	}
+
+	let intr_enabled = save_and_disable();
+
+	unsafe {
+		IRQ_MASK |= 1 << irq;
+
+		write_irq_mask();
+	}
+
+	restore(intr_enabled);
 }
 
-pub unsafe fn eoi() {
-	pio::out_byte(0x20, MASTER_ICW1_IOPORT_NUM);
-	pio::out_byte(0x20, SLAVE_ICW1_IOPORT_NUM);
+pub fn unmask_irq(irq: usize) {
+	if irq >= IRQ_CNT || irq == CASCADE_IRQ {
+		return
+	}
+
+	let intr_enabled = save_and_disable();
+
+	unsafe {
+		IRQ_MASK &= !(1 << irq);
+
+		write_irq_mask();
+	}
+
+	restore(intr_enabled);
+}
+
+unsafe fn write_irq_mask() {
+	if APIC_MODE {
+		for irq in 0..IRQ_CNT {
+			apic::set_irq_masked(irq, IRQ_MASK & (1 << irq) != 0);
+		}
+
+		return
+	}
+
+	pio::out_byte((IRQ_MASK & 0xff) as u32, MASTER_ICW2_IOPORT_NUM);
+	pio::out_byte((IRQ_MASK >> 8) as u32, SLAVE_ICW2_IOPORT_NUM);
+}
+
+// the lowest priority line of the controller is raised when interrupt request disappears
+// before acknowledge, such an interrupt is not reflected in the in-service register
+unsafe fn is_spurious(irq: usize) -> bool {
+	let cmd_port = match irq {
+		SPURIOUS_MASTER_IRQ => MASTER_ICW1_IOPORT_NUM,
+		SPURIOUS_SLAVE_IRQ => SLAVE_ICW1_IOPORT_NUM,
+		_ => return false
+	};
+
+	pio::out_byte(OCW3_READ_ISR, cmd_port);
+
+	pio::in_byte(cmd_port) & 0x80 == 0
 }
@@ -0,0 +1,479 @@
+use core::ptr;
+
+use crate::pio;
+use crate::intr;
+use crate::lock;
+use crate::work;
+use crate::console;
+
+const KBD_INTR_VEC_NUM: usize = 33;
+
+const KBD_DATA_IOPORT_NUM: u32 = 0x60;
+const KBD_STATUS_IOPORT_NUM: u32 = 0x64;
+
+// controller input buffer is full, data port can't be written
+const STATUS_INPUT_FULL_BIT: u32 = 0x2;
+
+const SET_LEDS_CMD: u32 = 0xed;
+
+const SCROLL_LOCK_LED: u32 = 0x1;
+const NUM_LOCK_LED: u32 = 0x2;
+const CAPS_LOCK_LED: u32 = 0x4;
+
+// scan code set 1 prefixes and keyboard responses
+const EXTENDED_PREFIX: u8 = 0xe0;
+const PAUSE_PREFIX: u8 = 0xe1;
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+const ERROR: u8 = 0xff;
+
+const KEY_RELEASED_BIT: u8 = 0x80;
+
+// pause key sends E1 1D 45 E1 9D C5 and has no release code
+const PAUSE_SEQ_TAIL_LEN: u8 = 2;
+
+// modifiers and lock keys state in key events
+pub const MOD_SHIFT: u8 = 0x1;
+pub const MOD_CTRL: u8 = 0x2;
+pub const MOD_ALT: u8 = 0x4;
+pub const MOD_CAPS_LOCK: u8 = 0x8;
+pub const MOD_NUM_LOCK: u8 = 0x10;
+pub const MOD_SCROLL_LOCK: u8 = 0x20;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+	// character key (unshifted character)
+	Char(u8),
+	// keypad character key (NumLock on or operator keys)
+	Keypad(u8),
+	Enter,
+	Backspace,
+	Tab,
+	Escape,
+	LeftShift,
+	RightShift,
+	LeftCtrl,
+	RightCtrl,
+	LeftAlt,
+	RightAlt,
+	CapsLock,
+	NumLock,
+	ScrollLock,
+	F(u8),
+	Up,
+	Down,
+	Left,
+	Right,
+	Home,
+	End,
+	PageUp,
+	PageDown,
+	Insert,
+	Delete,
+	Unknown(u8)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct KeyEvent {
+	pub key: Key,
+	pub pressed: bool,
+	// MOD_* bits after the event is processed
+	pub modifiers: u8,
+	// translated character, if any
+	pub chr: Option<u8>
+}
+
+const NULL_EVENT: KeyEvent = KeyEvent { key: Key::Unknown(0), pressed: false, modifiers: 0, chr: None };
+
+// scan code set 1 characters, 0 - not a character key
+static NORMAL_CHARS: [u8; 0x3a] = [ 0, 0x1b, b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'0', b'-', b'=', 0x08, b'\t',
+		b'q', b'w', b'e', b'r', b't', b'y', b'u', b'i', b'o', b'p', b'[', b']', b'\n', 0,
+		b'a', b's', b'd', b'f', b'g', b'h', b'j', b'k', b'l', b';', b'\'', b'`', 0, b'\\',
+		b'z', b'x', b'c', b'v', b'b', b'n', b'm', b',', b'.', b'/', 0, b'*', 0, b' ' ];
+
+static SHIFTED_CHARS: [u8; 0x3a] = [ 0, 0x1b, b'!', b'@', b'#', b'$', b'%', b'^', b'&', b'*', b'(', b')', b'_', b'+', 0x08, b'\t',
+		b'Q', b'W', b'E', b'R', b'T', b'Y', b'U', b'I', b'O', b'P', b'{', b'}', b'\n', 0,
+		b'A', b'S', b'D', b'F', b'G', b'H', b'J', b'K', b'L', b':', b'"', b'~', 0, b'|',
+		b'Z', b'X', b'C', b'V', b'B', b'N', b'M', b'<', b'>', b'?', 0, b'*', 0, b' ' ];
+
+// keypad keys 0x47 - 0x53
+const KEYPAD_FIRST_SCAN_CODE: u8 = 0x47;
+const KEYPAD_LAST_SCAN_CODE: u8 = 0x53;
+static KEYPAD_CHARS: [u8; 13] = *b"789-456+1230.";
+
+// pressed modifier keys
+const LEFT_SHIFT_DOWN: u16 = 0x1;
+const RIGHT_SHIFT_DOWN: u16 = 0x2;
+const LEFT_CTRL_DOWN: u16 = 0x4;
+const RIGHT_CTRL_DOWN: u16 = 0x8;
+const LEFT_ALT_DOWN: u16 = 0x10;
+const RIGHT_ALT_DOWN: u16 = 0x20;
+// lock keys are toggled on the first press only (typematic repeat sends press codes)
+const CAPS_LOCK_DOWN: u16 = 0x40;
+const NUM_LOCK_DOWN: u16 = 0x80;
+const SCROLL_LOCK_DOWN: u16 = 0x100;
+
+// set LEDs command progress, LED byte can be sent only after the command is acknowledged
+#[derive(Clone, Copy, PartialEq)]
+enum LedsCmd {
+	Idle,
+	CmdSent,
+	DataSent
+}
+
+struct KbdState {
+	// 0xe0 prefix received
+	extended: bool,
+	// pause sequence bytes to skip
+	pause_skip: u8,
+	keys_down: u16,
+	// lock keys MOD_* bits
+	locks: u8,
+	leds_cmd: LedsCmd,
+	// locks changed while set LEDs command was in progress
+	leds_update: bool
+}
+
+// scan codes are processed by the single worker task
+static mut STATE: KbdState = KbdState { extended: false, pause_skip: 0, keys_down: 0, locks: 0, leds_cmd: LedsCmd::Idle, leds_update: false };
+
+const EVENT_QUEUE_SIZE: usize = 32;
+
+// the oldest events are overwritten if events are not consumed
+struct EventQueue {
+	events: [KeyEvent; EVENT_QUEUE_SIZE],
+	head: usize,
+	len: usize
+}
+
+static EVENTS: lock::Mutex<EventQueue> = lock::Mutex::new(EventQueue { events: [NULL_EVENT; EVENT_QUEUE_SIZE], head: 0, len: 0 });
+
+pub fn init() {
+	intr::register(KBD_INTR_VEC_NUM, kbd_intr_handler);
+}
+
+pub fn next_event() -> Option<KeyEvent> {
+	let mut queue = EVENTS.lock();
+
+	if queue.len == 0 {
+		return None
+	}
+
+	let event = queue.events[queue.head];
+
+	queue.head = (queue.head + 1) % EVENT_QUEUE_SIZE;
+	queue.len -= 1;
+
+	Some(event)
+}
+
+fn kbd_intr_handler(_: &mut intr::InterruptFrame) {
+	// scan code has to be read to acknowledge the controller, translation is deferred
+	let scan_code = unsafe {
+		pio::in_byte(KBD_DATA_IOPORT_NUM)
+	};
+
+	work::schedule(process_scan_code, scan_code as usize);
+}
+
+// runs in worker task context with interrupts enabled
+fn process_scan_code(scan_code: usize) {
+	let scan_code = scan_code as u8;
+	let state = unsafe { &mut *ptr::addr_of_mut!(STATE) };
+
+	if state.pause_skip > 0 {
+		state.pause_skip -= 1;
+
+		return
+	}
+
+	match scan_code {
+		EXTENDED_PREFIX => {
+			state.extended = true;
+
+			return
+		},
+		PAUSE_PREFIX => {
+			state.pause_skip = PAUSE_SEQ_TAIL_LEN;
+
+			return
+		},
+		ACK => {
+			leds_cmd_ack(state);
+
+			return
+		},
+		// LED update is dropped if keyboard rejects it
+		RESEND | ERROR => {
+			state.leds_cmd = LedsCmd::Idle;
+
+			return
+		},
+		// key detection error or buffer overrun
+		0 => return,
+		_ => {}
+	}
+
+	let extended = state.extended;
+	state.extended = false;
+
+	let pressed = scan_code & KEY_RELEASED_BIT == 0;
+	let code = scan_code & !KEY_RELEASED_BIT;
+
+	// fake shifts surrounding extended keys
+	if extended && (code == 0x2a || code == 0x36) {
+		return
+	}
+
+	let key = key_of(code, extended, state.locks & MOD_NUM_LOCK != 0);
+
+	update_state(state, key, pressed);
+
+	let modifiers = modifiers(state);
+
+	let chr = if pressed {
+		translate(key, code, modifiers)
+	} else {
+		None
+	};
+
+	if pressed {
+		deliver_input(key, chr, modifiers);
+	}
+
+	push_event(KeyEvent { key, pressed, modifiers, chr });
+}
+
+fn key_of(code: u8, extended: bool, num_lock: bool) -> Key {
+	match (extended, code) {
+		(false, 0x01) => Key::Escape,
+		(false, 0x0e) => Key::Backspace,
+		(false, 0x0f) => Key::Tab,
+		(_, 0x1c) => Key::Enter,
+		(false, 0x1d) => Key::LeftCtrl,
+		(true, 0x1d) => Key::RightCtrl,
+		(false, 0x2a) => Key::LeftShift,
+		(false, 0x36) => Key::RightShift,
+		(false, 0x38) => Key::LeftAlt,
+		(true, 0x38) => Key::RightAlt,
+		(false, 0x3a) => Key::CapsLock,
+		(false, 0x45) => Key::NumLock,
+		(false, 0x46) => Key::ScrollLock,
+		(false, 0x3b..=0x44) => Key::F(code - 0x3a),
+		(false, 0x57) => Key::F(11),
+		(false, 0x58) => Key::F(12),
+		(true, 0x35) => Key::Keypad(b'/'),
+		(false, 0x37) => Key::Keypad(b'*'),
+		// navigation keys (dedicated or keypad with NumLock off)
+		(_, KEYPAD_FIRST_SCAN_CODE..=KEYPAD_LAST_SCAN_CODE) => {
+			let kp_chr = KEYPAD_CHARS[(code - KEYPAD_FIRST_SCAN_CODE) as usize];
+
+			if !extended && (num_lock || kp_chr == b'-' || kp_chr == b'+') {
+				return Key::Keypad(kp_chr)
+			}
+
+			match kp_chr {
+				b'7' => Key::Home,
+				b'8' => Key::Up,
+				b'9' => Key::PageUp,
+				b'4' => Key::Left,
+				b'6' => Key::Right,
+				b'1' => Key::End,
+				b'2' => Key::Down,
+				b'3' => Key::PageDown,
+				b'0' => Key::Insert,
+				b'.' => Key::Delete,
+				_ => Key::Unknown(code)
+			}
+		},
+		(false, _) if (code as usize) < NORMAL_CHARS.len() && NORMAL_CHARS[code as usize] != 0 => Key::Char(NORMAL_CHARS[code as usize]),
+		_ => Key::Unknown(code)
+	}
+}
+
+fn update_state(state: &mut KbdState, key: Key, pressed: bool) {
+	let (down_bit, lock_bit) = match key {
+		Key::LeftShift => (LEFT_SHIFT_DOWN, 0),
+		Key::RightShift => (RIGHT_SHIFT_DOWN, 0),
+		Key::LeftCtrl => (LEFT_CTRL_DOWN, 0),
+		Key::RightCtrl => (RIGHT_CTRL_DOWN, 0),
+		Key::LeftAlt => (LEFT_ALT_DOWN, 0),
+		Key::RightAlt => (RIGHT_ALT_DOWN, 0),
+		Key::CapsLock => (CAPS_LOCK_DOWN, MOD_CAPS_LOCK),
+		Key::NumLock => (NUM_LOCK_DOWN, MOD_NUM_LOCK),
+		Key::ScrollLock => (SCROLL_LOCK_DOWN, MOD_SCROLL_LOCK),
+		_ => return
+	};
+
+	if !pressed {
+		state.keys_down &= !down_bit;
+
+		return
+	}
+
+	if lock_bit != 0 && state.keys_down & down_bit == 0 {
+		state.locks ^= lock_bit;
+
+		set_leds(state);
+	}
+
+	state.keys_down |= down_bit;
+}
+
+fn modifiers(state: &KbdState) -> u8 {
+	let mut modifiers = state.locks;
+
+	if state.keys_down & (LEFT_SHIFT_DOWN | RIGHT_SHIFT_DOWN) != 0 {
+		modifiers |= MOD_SHIFT;
+	}
+
+	if state.keys_down & (LEFT_CTRL_DOWN | RIGHT_CTRL_DOWN) != 0 {
+		modifiers |= MOD_CTRL;
+	}
+
+	if state.keys_down & (LEFT_ALT_DOWN | RIGHT_ALT_DOWN) != 0 {
+		modifiers |= MOD_ALT;
+	}
+
+	modifiers
+}
+
+fn translate(key: Key, code: u8, modifiers: u8) -> Option<u8> {
+	match key {
+		Key::Char(c) => {
+			let mut shifted = modifiers & MOD_SHIFT != 0;
+
+			// CapsLock affects letters only
+			if c.is_ascii_lowercase() && modifiers & MOD_CAPS_LOCK != 0 {
+				shifted = !shifted;
+			}
+
+			// control characters, i.e. Ctrl-A is 0x01
+			if c.is_ascii_lowercase() && modifiers & MOD_CTRL != 0 {
+				return Some(c & 0x1f)
+			}
+
+			if shifted {
+				Some(SHIFTED_CHARS[code as usize])
+			} else {
+				Some(c)
+			}
+		},
+		Key::Keypad(c) => Some(c),
+		Key::Enter => Some(b'\n'),
+		Key::Backspace => Some(0x08),
+		Key::Tab => Some(b'\t'),
+		Key::Escape => Some(0x1b),
+		_ => None
+	}
+}
+
+// translated characters and VT100 sequences for non-character keys go to console input buffer
+fn deliver_input(key: Key, chr: Option<u8>, modifiers: u8) {
+	let shift = modifiers & MOD_SHIFT != 0;
+
+	let seq: &[u8] = match key {
+		// scrollback paging
+		Key::PageUp if shift => return console::page_up(),
+		Key::PageDown if shift => return console::page_down(),
+		Key::Up => b"\x1b[A",
+		Key::Down => b"\x1b[B",
+		Key::Right => b"\x1b[C",
+		Key::Left => b"\x1b[D",
+		Key::Home => b"\x1b[H",
+		Key::End => b"\x1b[F",
+		Key::Insert => b"\x1b[2~",
+		Key::Delete => b"\x1b[3~",
+		Key::PageUp => b"\x1b[5~",
+		Key::PageDown => b"\x1b[6~",
+		_ => match chr {
+			Some(c) => {
+				console::KBD_BUF.push_back(c);
+
+				return
+			},
+			_ => return
+		}
+	};
+
+	for b in seq {
+		console::KBD_BUF.push_back(*b);
+	}
+}
+
+fn push_event(event: KeyEvent) {
+	let mut queue = EVENTS.lock();
+
+	let tail = (queue.head + queue.len) % EVENT_QUEUE_SIZE;
+	queue.events[tail] = event;
+
+	if queue.len < EVENT_QUEUE_SIZE {
+		queue.len += 1;
+	} else {
+		queue.head = (queue.head + 1) % EVENT_QUEUE_SIZE;
+	}
+}
+
+// starts set LEDs command (or postpones it until the current one is completed)
+fn set_leds(state: &mut KbdState) {
+	if state.leds_cmd != LedsCmd::Idle {
+		state.leds_update = true;
+
+		return
+	}
+
+	state.leds_cmd = LedsCmd::CmdSent;
+
+	unsafe {
+		write_data(SET_LEDS_CMD);
+	}
+}
+
+// keyboard acknowledges both command and LED bytes
+fn leds_cmd_ack(state: &mut KbdState) {
+	match state.leds_cmd {
+		LedsCmd::CmdSent => {
+			// the latest locks state is sent
+			state.leds_cmd = LedsCmd::DataSent;
+			state.leds_update = false;
+
+			unsafe {
+				write_data(leds(state.locks));
+			}
+		},
+		LedsCmd::DataSent => {
+			state.leds_cmd = LedsCmd::Idle;
+
+			if state.leds_update {
+				state.leds_update = false;
+
+				set_leds(state);
+			}
+		},
+		LedsCmd::Idle => {}
+	}
+}
+
+fn leds(locks: u8) -> u32 {
+	let mut leds = 0;
+
+	if locks & MOD_SCROLL_LOCK != 0 {
+		leds |= SCROLL_LOCK_LED;
+	}
+
+	if locks & MOD_NUM_LOCK != 0 {
+		leds |= NUM_LOCK_LED;
+	}
+
+	if locks & MOD_CAPS_LOCK != 0 {
+		leds |= CAPS_LOCK_LED;
+	}
+
+	leds
+}
+
+unsafe fn write_data(b: u32) {
+	while pio::in_byte(KBD_STATUS_IOPORT_NUM) & STATUS_INPUT_FULL_BIT != 0 {}
+
+	pio::out_byte(b, KBD_DATA_IOPORT_NUM);
+}
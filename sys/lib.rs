@@ -6,6 +6,14 @@ macro_rules! console_println {
 	( $f:expr, $( $a:expr ), * ) => ( { crate::console::print(format_args!(concat!($f, "\n"), $( $a ), *)); } )
 }
 
+// typed boot parameter declaration, i.e. param!("loglevel", u32, 1)
+#[macro_export]
+macro_rules! param {
+	( $n:expr, $t:ty, $d:expr ) => ( $crate::param::Param::<$t>::new($n, $d) )
+}
+
+pub mod param;
+
 pub mod console;
 
 pub mod task;
@@ -23,3 +31,29 @@ pub mod pio;
 pub mod intr;
 
 pub mod string;
+
+pub mod boot;
+
+pub mod timer;
+
+pub mod serial;
+
+pub mod gdt;
+
+pub mod vm;
+
+pub mod frame;
+
+pub mod elf;
+
+pub mod syscall;
+
+pub mod exception;
+
+pub mod backtrace;
+
+pub mod apic;
+
+pub mod work;
+
+pub mod kbd;
@@ -3,6 +3,7 @@ use core::ops;
 use core::cell;
 
 use crate::task;
+use crate::intr;
 
 pub struct Mutex<T> {
 	guarded: cell::UnsafeCell<T>,
@@ -21,7 +22,7 @@ impl<T> Mutex<T> {
 		}
 	}
 
-	pub fn lock(&self) -> MutexGuard<T> {
+	pub fn lock(&self) -> MutexGuard<'_, T> {
 		while self.lock.swap(true, atomic::Ordering::SeqCst) {
 			task::suspend();
 		}
@@ -31,6 +32,10 @@ impl<T> Mutex<T> {
 		}
 	}
 
+	pub fn is_locked(&self) -> bool {
+		self.lock.load(atomic::Ordering::SeqCst)
+	}
+
 	pub fn unlock(&self) {
 		self.lock.store(false, atomic::Ordering::SeqCst);
 	}
@@ -63,3 +68,92 @@ impl<T> Drop for MutexGuard<'_, T> {
 unsafe impl<T> Send for Mutex<T> {}
 
 unsafe impl<T> Sync for Mutex<T> {}
+
+// spin lock for data shared with interrupt handlers, interrupts are disabled while lock is held
+// (holder can't be preempted, so lock should be held for short periods only)
+pub struct IrqMutex<T> {
+	guarded: cell::UnsafeCell<T>,
+	lock: atomic::AtomicBool
+}
+
+pub struct IrqMutexGuard<'a, T> {
+	mutex: &'a IrqMutex<T>,
+	// interrupts state restored on unlock
+	intr_enabled: bool
+}
+
+impl<T> IrqMutex<T> {
+	pub const fn new(obj: T) -> IrqMutex<T> {
+		IrqMutex {
+			guarded: cell::UnsafeCell::new(obj),
+			lock: atomic::AtomicBool::new(false)
+		}
+	}
+
+	pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+		let intr_enabled = intr::save_and_disable();
+
+		while self.lock.swap(true, atomic::Ordering::SeqCst) {
+			core::hint::spin_loop();
+		}
+
+		IrqMutexGuard {
+			mutex: self,
+			intr_enabled
+		}
+	}
+
+	pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T>> {
+		let intr_enabled = intr::save_and_disable();
+
+		if self.lock.swap(true, atomic::Ordering::SeqCst) {
+			intr::restore(intr_enabled);
+
+			return None
+		}
+
+		Some(IrqMutexGuard {
+			mutex: self,
+			intr_enabled
+		})
+	}
+
+	pub fn is_locked(&self) -> bool {
+		self.lock.load(atomic::Ordering::SeqCst)
+	}
+
+	// access bypassing the lock, for emergency paths (panic and fault reports) only
+	pub unsafe fn get_unlocked(&self) -> &mut T {
+		&mut *self.guarded.get()
+	}
+}
+
+impl<T> ops::Deref for IrqMutexGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		unsafe {
+			&*self.mutex.guarded.get()
+		}
+	}
+}
+
+impl<T> ops::DerefMut for IrqMutexGuard<'_, T> {
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe {
+			&mut *self.mutex.guarded.get()
+		}
+	}
+}
+
+impl<T> Drop for IrqMutexGuard<'_, T> {
+	fn drop(&mut self) {
+		self.mutex.lock.store(false, atomic::Ordering::SeqCst);
+
+		intr::restore(self.intr_enabled);
+	}
+}
+
+unsafe impl<T> Send for IrqMutex<T> {}
+
+unsafe impl<T> Sync for IrqMutex<T> {}
@@ -10,6 +10,7 @@ use core::usize;
 use core::ptr;
 use core::sync::atomic::{ AtomicBool, Ordering };
 use core::str;
+use core::slice;
 
 use uos::console;
 use uos::task;
@@ -17,57 +18,96 @@ use uos::pio;
 use uos::intr;
 use uos::vec;
 use uos::string as ustr;
-
-const DIVIDE_ERROR_INTR_VEC_NUM: usize = 0;
-const GENERAL_PROTECTION_ERR_VEC_NUM: usize = 13;
+use uos::boot;
+use uos::param;
+use uos::timer;
+use uos::gdt;
+use uos::vm;
+use uos::frame;
+use uos::elf;
+use uos::backtrace;
+use uos::exception;
+use uos::syscall;
+use uos::work;
+use uos::kbd;
 
 const TIMER_INTR_VEC_NUM: usize = 32;
-const KBD_INTR_VEC_NUM: usize = 33;
 
 const CMOS_RAM_CMD_PORT_NUM: u32 = 0x70;
 const CMOS_RAM_DATA_PORT_NUM: u32 = 0x71;
 
-const KBD_DATA_IOPORT_NUM: u32 = 0x60;
-const KEY_RELEASED_BIT_MASK: u32 = 0x80;
+#[link(name = "uos")]
+extern {
+	// user mode test task code and it's progress counter
+	fn user_test_task();
 
-// required for modifier key release case handling
-// const KEY_SCAN_CODE_MASK: u32 = !KEY_RELEASED_BIT_MASK;
+	static user_test_counter: u32;
+}
 
-// standard key codes
-static KBD_SCAN_CODES: [u8; 83] = [ 0, 0, b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'0', b'-', b'=', 0, 0, b'q', b'w', b'e', b'r', b't', b'y', b'u', b'i', b'o', b'p', b'[', b']', b'\n', 0, 
-		b'a', b's', b'd', b'f', b'g', b'h', b'j', b'k', b'l', b';', b'\'', b'`', 0, b'\\', b'z', b'x', b'c', b'v', b'b', b'n', b'm', b',', b'.', b'/', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 
-		0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0 ];
+static USER_TEST_STARTED: AtomicBool = AtomicBool::new(false);
 
-static SUSPEND_IDLE_TASK: AtomicBool = AtomicBool::new(false);
+// 0 - quiet boot, 1 - boot information
+static LOG_LEVEL: param::Param<u32> = param!("loglevel", u32, 1);
 
 pub struct BssInfo {
 	addr: *mut u8,
 	size: usize
 }
 
+// entry point used by 2nd stage loader (called from _start)
 #[no_mangle]
-pub unsafe extern fn _start(bss_info: BssInfo) {
+pub unsafe extern fn loader_start(bss_info: BssInfo, boot_drive: usize, cmdline: *const u8) {
 	// zeroing out BSS section (ideally this initialization should be fenced by atomic flag of some sort)
 	ptr::write_bytes(bss_info.addr, 0, bss_info.size);
+
+	boot::init_stage2(boot_drive as u8, cmdline);
 	
 	init();
 }
 
+// entry point used by multiboot compliant loaders, BSS section is zeroed by loader
+#[no_mangle]
+pub unsafe extern fn multiboot_start(mbi: *const boot::MultibootInfo) {
+	boot::init_multiboot(mbi);
+
+	init();
+}
+
 unsafe fn init() {
+	param::init(boot::info().cmdline());
+
+	console::init();
 	console::clear();
 
 	console_println!("RobCo UOS v 0.1");
 
-	// registering mandatory interrupt handlers
-	intr::register_handler(DIVIDE_ERROR_INTR_VEC_NUM, divide_error);
-	intr::register_handler_with_err_code(GENERAL_PROTECTION_ERR_VEC_NUM, general_protection_error);
+	if param::cmdline_truncated() {
+		console_println!("command line is longer than {} bytes, the rest is ignored", param::CMDLINE_MAX_LEN);
+	}
+
+	// replacing loader provided GDT
+	gdt::init();
+
+	vm::init();
+
+	frame::init();
+
+	if LOG_LEVEL.get() > 0 {
+		print_boot_info();
+	}
 
 	// registering HW interrupt handlers
-	intr::register_handler(TIMER_INTR_VEC_NUM, timer_intr_handler);
-	intr::register_handler(KBD_INTR_VEC_NUM, kbd_intr_handler);
+	intr::register(TIMER_INTR_VEC_NUM, timer_intr_handler);
+	kbd::init();
 
-	// programmable interrupt controller initialization
-	intr::init_pic();
+	timer::init();
+
+	// interrupt controller initialization (APIC if present, legacy PICs otherwise)
+	intr::init();
+
+	if LOG_LEVEL.get() > 0 {
+		console_println!("interrupt controller: {}", if intr::is_apic_mode() { "APIC" } else { "PIC" });
+	}
 
 	init_ata_hdd();
 
@@ -76,6 +116,9 @@ unsafe fn init() {
 
 	task::create(idle_thread);
 
+	// deferred interrupt processing
+	task::create(work::worker);
+
 	let mut cmd_buf: vec::Vec<u8> = vec::Vec::with_cap(64);
 	loop {
 		console::print_str("> ");
@@ -86,12 +129,139 @@ unsafe fn init() {
 		let cmd = str::from_utf8(&cmd_buf).unwrap();
 		if ustr::cmp(cmd, "ps") == 0 {
 			console_println!("print task list");
+		} else if ustr::cmp(cmd, "irqstat") == 0 {
+			print_irq_stat();
+		} else if ustr::cmp(cmd, "user") == 0 {
+			user_test();
+		} else if cmd.starts_with("run ") {
+			let mut args: vec::Vec<&str> = vec::Vec::new();
+			for arg in cmd[4..].split(' ').filter(|a| !a.is_empty()) {
+				args.push(arg);
+			}
+
+			run_program(&args);
 		} else {
 			console_println!("unknown command: '{}'", cmd);
 		}
 	}
 }
 
+fn print_boot_info() {
+	let boot_info = boot::info();
+
+	if boot_info.loader == boot::BootLoader::Multiboot {
+		console_println!("booted by multiboot loader, lower mem: {}k, upper mem: {}k", boot_info.mem_lower, boot_info.mem_upper);
+	}
+
+	let cmdline = param::cmdline();
+	if !cmdline.is_empty() {
+		console_println!("cmdline: {}", cmdline);
+	}
+
+	if let Some(drive) = boot_info.boot_drive {
+		console_println!("boot drive: {:x}", drive);
+	}
+
+	for r in boot_info.mem_regions() {
+		let avail = if r.kind == boot::MEM_REGION_AVAILABLE {
+			"available"
+		} else {
+			"reserved"
+		};
+
+		console_println!("mem {:x} - {:x} {}", r.base, r.base + r.len, avail);
+	}
+
+	for m in boot_info.modules() {
+		console_println!("module '{}' at {:x} size {}", m.name(), m.start, m.size());
+	}
+}
+
+unsafe fn user_test() {
+	if !USER_TEST_STARTED.swap(true, Ordering::SeqCst) {
+		// test task gets it's own address space, system image part is shared
+		match vm::create_addr_space() {
+			Some(pdbr) => {
+				if task::create_user(user_test_task as *const () as usize, pdbr).is_none() {
+					console_println!("failed to create user task stack");
+
+					vm::destroy_addr_space(pdbr);
+				}
+			},
+			_ => console_println!("failed to create user task address space")
+		}
+	}
+
+	console_println!("user task counter: {}", ptr::read_volatile(&user_test_counter));
+}
+
+// interrupts counters table (vectors which never fired are skipped)
+fn print_irq_stat() {
+	let chip = if intr::is_apic_mode() { "IO-APIC" } else { "XT-PIC" };
+
+	console_println!(" vec      count  source");
+
+	for vec_num in 0..intr::VEC_CNT {
+		let cnt = intr::intr_cnt(vec_num);
+		if cnt == 0 {
+			continue
+		}
+
+		if vec_num < exception::EXCEPTIONS_CNT {
+			console_println!("{:>4}: {:>10}  exception {}", vec_num, cnt, exception::name(vec_num));
+		} else if vec_num >= intr::IRQ_BASE_VEC_NUM && vec_num < intr::IRQ_BASE_VEC_NUM + intr::IRQ_CNT {
+			let irq = vec_num - intr::IRQ_BASE_VEC_NUM;
+			console_println!("{:>4}: {:>10}  {} IRQ {} ({} handlers)", vec_num, cnt, chip, irq, intr::handlers_cnt(irq));
+		} else if vec_num == task::SWITCH_TASK_VEC_NUM {
+			console_println!("{:>4}: {:>10}  task switch", vec_num, cnt);
+		} else if vec_num == syscall::SYSCALL_VEC_NUM {
+			console_println!("{:>4}: {:>10}  system call", vec_num, cnt);
+		} else {
+			console_println!("{:>4}: {:>10}", vec_num, cnt);
+		}
+	}
+
+	console_println!(" SPU: {:>10}  spurious", intr::spurious_cnt());
+	console_println!(" UNH: {:>10}  unhandled", intr::unhandled_cnt());
+	console_println!(" WRK: {:>10}  deferred work pending, {} dropped", work::pending_cnt(), work::dropped_cnt());
+}
+
+// starts program loaded as a boot module, module name is matched without path
+fn run_program(args: &[&str]) {
+	if args.is_empty() {
+		console_println!("usage: run <program> [args]");
+		return
+	}
+
+	let module = boot::info().modules().iter().find(|m| {
+		let mod_path = m.name().split(' ').next().unwrap_or("");
+		ustr::cmp(mod_path.rsplit('/').next().unwrap_or(""), args[0]) == 0
+	});
+
+	let module = match module {
+		Some(m) => m,
+		_ => {
+			console_println!("program not found: {}", args[0]);
+			return
+		}
+	};
+
+	// only identity mapped memory is accessible
+	if module.end > vm::USER_SPACE_START {
+		console_println!("program image is not accessible: {}", args[0]);
+		return
+	}
+
+	let image = unsafe {
+		slice::from_raw_parts(module.start as *const u8, module.size())
+	};
+
+	match elf::exec(image, args) {
+		Ok(tid) => console_println!("started task {}", tid),
+		Err(e) => console_println!("failed to start {}: {:?}", args[0], e)
+	}
+}
+
 unsafe fn init_ata_hdd() {
 	// checking disk type
 	pio::out_byte(0x12, CMOS_RAM_CMD_PORT_NUM);
@@ -106,58 +276,45 @@ unsafe fn init_ata_hdd() {
 	}
 }
 
-extern fn divide_error() {
-	console_println!("divide error");
+// EOI and preemption are done by interrupt dispatcher
+fn timer_intr_handler(_: &mut intr::InterruptFrame) {
+	timer::tick();
 }
 
-extern fn general_protection_error(err_code: usize) {
-	console_println!("general protection error: {:x}", err_code);
-
-	loop {}
-}
+// interrupt can wake up parked task (i.e. worker), so processor is given away after each one
+fn idle_thread() {
+	loop {
+		unsafe {
+			intr::wait_intr();
+		}
 
-extern fn timer_intr_handler() {
-	unsafe {
-		intr::eoi();
+		task::suspend();
 	}
 }
 
-extern fn kbd_intr_handler() {
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
 	unsafe {
-		let key_scan_code = pio::in_byte(KBD_DATA_IOPORT_NUM);
+		intr::intr_disable();
 
-		// deciding was it key press or key release
-		if (key_scan_code & KEY_RELEASED_BIT_MASK) == 0 {
-			// TODO scan table range check
-			console::KBD_BUF.push_back(KBD_SCAN_CODES[key_scan_code as usize]);
+		console::enter_panic_mode();
+	}
 
-			// setting suspend idle task flag to give a chance to drain keyboard buffer
-			SUSPEND_IDLE_TASK.store(true, Ordering::SeqCst);
-		} else {
-			// TODO reset modifier key state ( i.e. Shift key released )
-		}
+	console_println!("kernel {}", info);
 
-		intr::eoi();
+	// task queue can be locked by panicked code
+	match task::try_curr_task_id() {
+		Some(tid) => console_println!("current task: {}", tid),
+		_ => console_println!("current task: unknown")
 	}
-}
 
-fn idle_thread() {
-	loop {
-		if SUSPEND_IDLE_TASK.load(Ordering::SeqCst) {
-			// reset idle task suspend flag, making idle task eligible for scheduling again
-			SUSPEND_IDLE_TASK.store(false, Ordering::SeqCst);
+	backtrace::print_current();
 
-			task::suspend();
-		}
+	unsafe {
+		intr::halt();
 	}
 }
 
-#[panic_handler]
-fn panic(_: &PanicInfo) -> ! {
-	// making some sign that we reached this place
-	loop {}
-}
-
 // the following functions make linker happy
 #[no_mangle]
 extern fn rust_eh_personality() {}
@@ -0,0 +1,128 @@
+use core::str;
+
+use crate::string as ustr;
+
+// should be kept in sync with the loader (boot configuration sector size)
+pub const CMDLINE_MAX_LEN: usize = 512;
+
+// command line copy, boot loader memory holding the original can be reused
+static mut CMDLINE: [u8; CMDLINE_MAX_LEN] = [0; CMDLINE_MAX_LEN];
+static mut CMDLINE_LEN: usize = 0;
+static mut CMDLINE_TRUNCATED: bool = false;
+
+pub trait ParamValue: Sized + Copy {
+	// value is None for parameters specified without '=' (i.e. flags)
+	fn parse(val: Option<&'static str>) -> Option<Self>;
+}
+
+impl ParamValue for u32 {
+	fn parse(val: Option<&'static str>) -> Option<u32> {
+		val.and_then(|v| v.parse::<u32>().ok())
+	}
+}
+
+impl ParamValue for usize {
+	fn parse(val: Option<&'static str>) -> Option<usize> {
+		val.and_then(|v| v.parse::<usize>().ok())
+	}
+}
+
+impl ParamValue for bool {
+	fn parse(val: Option<&'static str>) -> Option<bool> {
+		match val {
+			None => Some(true),
+			Some(v) => {
+				if ustr::cmp(v, "1") == 0 || ustr::cmp(v, "on") == 0 || ustr::cmp(v, "true") == 0 {
+					Some(true)
+				} else if ustr::cmp(v, "0") == 0 || ustr::cmp(v, "off") == 0 || ustr::cmp(v, "false") == 0 {
+					Some(false)
+				} else {
+					None
+				}
+			}
+		}
+	}
+}
+
+impl ParamValue for &'static str {
+	fn parse(val: Option<&'static str>) -> Option<&'static str> {
+		val
+	}
+}
+
+// typed boot parameter declaration, see param! macro
+pub struct Param<T> {
+	name: &'static str,
+	default: T
+}
+
+impl<T> Param<T> {
+	pub const fn new(name: &'static str, default: T) -> Param<T> {
+		Param { name, default }
+	}
+
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+}
+
+impl<T: ParamValue> Param<T> {
+	// returns default value if parameter is missing or it's value is malformed
+	pub fn get(&self) -> T {
+		match lookup(self.name) {
+			Some(val) => T::parse(val).unwrap_or(self.default),
+			_ => self.default
+		}
+	}
+}
+
+pub unsafe fn init(cmdline: &str) {
+	let src = cmdline.as_bytes();
+
+	let mut len = src.len();
+	if len > CMDLINE_MAX_LEN {
+		len = CMDLINE_MAX_LEN;
+		CMDLINE_TRUNCATED = true;
+	}
+
+	CMDLINE[..len].copy_from_slice(&src[..len]);
+	CMDLINE_LEN = len;
+}
+
+pub fn cmdline() -> &'static str {
+	unsafe {
+		match str::from_utf8(&CMDLINE[..CMDLINE_LEN]) {
+			Ok(s) => s.trim(),
+			// truncation can split multibyte character
+			Err(e) => str::from_utf8_unchecked(&CMDLINE[..e.valid_up_to()]).trim()
+		}
+	}
+}
+
+pub fn cmdline_truncated() -> bool {
+	unsafe {
+		CMDLINE_TRUNCATED
+	}
+}
+
+fn is_separator(c: char) -> bool {
+	c == ' ' || c == '\t' || c == '\n' || c == '\r'
+}
+
+// the last occurrence wins
+fn lookup(name: &str) -> Option<Option<&'static str>> {
+	let mut found = None;
+
+	for arg in cmdline().split(is_separator) {
+		let (arg_name, arg_val) = match arg.find('=') {
+			Some(pos) => (&arg[..pos], Some(&arg[pos + 1..])),
+			_ => (arg, None)
+		};
+
+		if ustr::cmp(arg_name, name) == 0 {
+			found = Some(arg_val);
+		}
+	}
+
+	found
+}
@@ -0,0 +1,37 @@
+use crate::pio;
+
+const COM1_IOPORT_NUM: u32 = 0x3f8;
+
+const DATA_IOPORT_NUM: u32 = COM1_IOPORT_NUM;
+const INTR_ENABLE_IOPORT_NUM: u32 = COM1_IOPORT_NUM + 1;
+const FIFO_CTRL_IOPORT_NUM: u32 = COM1_IOPORT_NUM + 2;
+const LINE_CTRL_IOPORT_NUM: u32 = COM1_IOPORT_NUM + 3;
+const MODEM_CTRL_IOPORT_NUM: u32 = COM1_IOPORT_NUM + 4;
+const LINE_STATUS_IOPORT_NUM: u32 = COM1_IOPORT_NUM + 5;
+
+const LINE_STATUS_THR_EMPTY: u32 = 0x20;
+
+pub unsafe fn init() {
+	// polling mode, no interrupts
+	pio::out_byte(0, INTR_ENABLE_IOPORT_NUM);
+
+	// setting 38400 baud rate divisor
+	pio::out_byte(0x80, LINE_CTRL_IOPORT_NUM);
+	pio::out_byte(3, DATA_IOPORT_NUM);
+	pio::out_byte(0, INTR_ENABLE_IOPORT_NUM);
+
+	// 8 bits, no parity, 1 stop bit
+	pio::out_byte(0x3, LINE_CTRL_IOPORT_NUM);
+
+	// enabling and clearing FIFO
+	pio::out_byte(0xc7, FIFO_CTRL_IOPORT_NUM);
+
+	// DTR and RTS
+	pio::out_byte(0x3, MODEM_CTRL_IOPORT_NUM);
+}
+
+pub unsafe fn write_byte(b: u8) {
+	while (pio::in_byte(LINE_STATUS_IOPORT_NUM) & LINE_STATUS_THR_EMPTY) == 0 {}
+
+	pio::out_byte(b as u32, DATA_IOPORT_NUM);
+}
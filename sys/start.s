@@ -0,0 +1,127 @@
+# system image entry point shared by 2nd stage loader and multiboot compliant loaders
+
+.equ MULTIBOOT_HEADER_MAGIC, 0x1badb002
+# page aligned modules and memory information request
+.equ MULTIBOOT_HEADER_FLAGS, 0x3
+.equ MULTIBOOT_BOOTLOADER_MAGIC, 0x2badb002
+
+.equ CODE_SEG_SEL, 0x8
+.equ DATA_SEG_SEL, 0x10
+
+# the same stack location as the one configured by 2nd stage loader
+.equ SYS_STACK_TOP, 0x30000 - 4
+# re-mapped BIOS data area location (should be kept in sync with the loader)
+.equ MEM_ORIGIN_VIRT_ADDR, 0x40000
+
+.equ PG_PRESENT_RW, 0x3
+.equ CR0_PG_BIT, 0x80000000
+
+.section .multiboot, "a"
+
+.align 4
+.int MULTIBOOT_HEADER_MAGIC
+.int MULTIBOOT_HEADER_FLAGS
+.int -(MULTIBOOT_HEADER_MAGIC + MULTIBOOT_HEADER_FLAGS)
+
+.section .data
+
+# multiboot loader GDT can't be trusted, using our own copy of loader GDT
+.align 8
+boot_gdt:
+# null segment descriptor
+.fill 8
+
+# system level code segment descriptor
+.short 0xffff
+.short 0
+.byte 0
+.byte 0x98
+.byte 0xcf
+.byte 0
+
+# system level data segment descriptor
+.short 0xffff
+.short 0
+.byte 0
+.byte 0x92
+.byte 0xcf
+.byte 0
+
+boot_gdt_end:
+
+boot_gdt_info:
+.short boot_gdt_end - boot_gdt - 1
+.int boot_gdt
+
+.section .bss
+
+.align 4096
+boot_page_dir:
+.skip 4096
+
+boot_page_tbl:
+.skip 4096
+
+.section .text
+
+.global _start
+_start:
+
+cmpl $MULTIBOOT_BOOTLOADER_MAGIC, %eax
+je multiboot_entry
+
+# started by 2nd stage loader, paging is already enabled and BssInfo struct is on the stack
+jmp loader_start
+
+multiboot_entry:
+
+lgdt boot_gdt_info
+
+ljmp $CODE_SEG_SEL, $reload_segs
+
+reload_segs:
+movw $DATA_SEG_SEL, %ax
+
+movw %ax, %ds
+movw %ax, %ss
+movw %ax, %es
+movw %ax, %gs
+movw %ax, %fs
+
+movl $SYS_STACK_TOP, %esp
+
+# identity mapping for the first 4mb (ebx holds multiboot info pointer and should be preserved)
+movl $0, %ecx
+
+fill_pg_tbl:
+movl %ecx, %eax
+shll $12, %eax
+orl $PG_PRESENT_RW, %eax
+movl %eax, boot_page_tbl(, %ecx, 4)
+
+incl %ecx
+cmpl $1024, %ecx
+jne fill_pg_tbl
+
+# making first 4k of conventional memory accessible at the same address as in the loader VM map
+movl $PG_PRESENT_RW, boot_page_tbl + (MEM_ORIGIN_VIRT_ADDR >> 10)
+
+movl $boot_page_tbl, %eax
+orl $PG_PRESENT_RW, %eax
+movl %eax, boot_page_dir
+
+movl $boot_page_dir, %eax
+movl %eax, %cr3
+
+# enabling paging
+movl %cr0, %eax
+orl $CR0_PG_BIT, %eax
+movl %eax, %cr0
+
+# passing multiboot info pointer
+pushl %ebx
+
+# pushing bogus return value on stack cause we don't have any chances to return here
+pushl $0
+
+jmp multiboot_start
@@ -1,3 +1,6 @@
+use core::str;
+use core::slice;
+
 pub fn cmp(s1: &str, s2: &str) -> i32 {
 	let s1b = s1.as_bytes();
 	let s2b = s2.as_bytes();
@@ -9,6 +12,9 @@ pub fn cmp(s1: &str, s2: &str) -> i32 {
 	}
 
 	let s_len = s1b.len();
+	if s_len == 0 {
+		return 0
+	}
 
 	let mut i: usize = 0;
 	while s1b[i] == s2b[i] {
@@ -28,3 +34,16 @@ pub fn cmp(s1: &str, s2: &str) -> i32 {
 	}
 }
 
+
+// null terminated string located at specified address
+pub unsafe fn from_cstr(s: *const u8) -> &'static str {
+	let mut len: usize = 0;
+	while *s.wrapping_add(len) != 0 {
+		len += 1;
+	}
+
+	match str::from_utf8(slice::from_raw_parts(s, len)) {
+		Ok(rs) => rs,
+		_ => ""
+	}
+}
@@ -0,0 +1,175 @@
+use core::mem;
+use core::slice;
+
+use crate::task;
+use crate::console;
+use crate::vm;
+use crate::intr;
+
+// the same as in arch.s
+pub const SYSCALL_VEC_NUM: usize = 0x80;
+
+// system call number is passed in eax, arguments in ebx, ecx, edx, esi and edi, result is returned in eax
+pub const SYS_YIELD: usize = 0;
+pub const SYS_EXIT: usize = 1;
+pub const SYS_SLEEP: usize = 2;
+pub const SYS_WRITE: usize = 3;
+pub const SYS_READ: usize = 4;
+pub const SYS_GETPID: usize = 5;
+pub const SYS_SPAWN: usize = 6;
+pub const SYS_BRK: usize = 7;
+
+pub const EBADF: isize = -9;
+pub const EFAULT: isize = -14;
+pub const EINVAL: isize = -22;
+pub const ENOSYS: isize = -38;
+
+const STDIN_FD: usize = 0;
+const STDOUT_FD: usize = 1;
+const STDERR_FD: usize = 2;
+
+// registers saved by syscall_entry, layout is the same as in the task CPU state
+#[repr(C)]
+pub struct SyscallFrame {
+	ds: u32,
+	edi: u32,
+	esi: u32,
+	ebp: u32,
+	esp: u32,
+	ebx: u32,
+	edx: u32,
+	ecx: u32,
+	eax: u32,
+	eip: u32,
+	cs: u32,
+	eflags: u32,
+	user_esp: u32,
+	ss: u32
+}
+
+type SyscallFn = fn(usize, usize, usize, usize, usize) -> isize;
+
+const SYSCALLS: [SyscallFn; 8] = [
+	sys_yield,
+	sys_exit,
+	sys_sleep,
+	sys_write,
+	sys_read,
+	sys_getpid,
+	sys_spawn,
+	sys_brk
+];
+
+#[no_mangle]
+pub unsafe extern fn syscall_dispatch(frame: *mut SyscallFrame) {
+	let frame = &mut *frame;
+
+	intr::count(SYSCALL_VEC_NUM);
+
+	let num = frame.eax as usize;
+
+	let rv = if num < SYSCALLS.len() {
+		SYSCALLS[num](frame.ebx as usize, frame.ecx as usize, frame.edx as usize, frame.esi as usize, frame.edi as usize)
+	} else {
+		ENOSYS
+	};
+
+	frame.eax = rv as u32;
+}
+
+fn sys_yield(_: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+	task::suspend();
+
+	0
+}
+
+fn sys_exit(_: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+	task::exit();
+}
+
+fn sys_sleep(ms: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+	task::sleep(ms as u32);
+
+	0
+}
+
+fn sys_write(fd: usize, buf: usize, len: usize, _: usize, _: usize) -> isize {
+	if fd != STDOUT_FD && fd != STDERR_FD {
+		return EBADF
+	}
+
+	if !vm::is_user_accessible(buf, len, false) {
+		return EFAULT
+	}
+
+	let bs = unsafe {
+		slice::from_raw_parts(buf as *const u8, len)
+	};
+
+	console::print_bytes(bs);
+
+	len as isize
+}
+
+// reads characters until buffer is full or end of line is reached
+fn sys_read(fd: usize, buf: usize, len: usize, _: usize, _: usize) -> isize {
+	if fd != STDIN_FD {
+		return EBADF
+	}
+
+	if !vm::is_user_accessible(buf, len, true) {
+		return EFAULT
+	}
+
+	let bs = unsafe {
+		slice::from_raw_parts_mut(buf as *mut u8, len)
+	};
+
+	let mut read = 0;
+	while read < len {
+		let chr = console::read_char();
+
+		bs[read] = chr;
+		read += 1;
+
+		if chr == b'\n' {
+			break;
+		}
+	}
+
+	read as isize
+}
+
+fn sys_getpid(_: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+	task::curr_task_id() as isize
+}
+
+// starts new user mode thread at the specified entry point, stack is allocated by the caller
+fn sys_spawn(entry: usize, stack_top: usize, _: usize, _: usize, _: usize) -> isize {
+	if entry == 0 || stack_top == 0 {
+		return EINVAL
+	}
+
+	if !vm::is_user_accessible(entry, 1, false) {
+		return EFAULT
+	}
+
+	// at least the top stack word should be writable
+	if stack_top < mem::size_of::<u32>() || !vm::is_user_accessible(stack_top - mem::size_of::<u32>(), mem::size_of::<u32>(), true) {
+		return EFAULT
+	}
+
+	// new thread shares address space with the calling task
+	task::create_user_with_stack(entry, vm::curr_pdbr(), stack_top) as isize
+}
+
+// extends heap of the calling task address space (0 returns current program break)
+fn sys_brk(addr: usize, _: usize, _: usize, _: usize, _: usize) -> isize {
+	let pdbr = vm::curr_pdbr();
+
+	if pdbr == vm::kernel_pdbr() {
+		return EINVAL
+	}
+
+	vm::grow_heap(pdbr, addr) as isize
+}
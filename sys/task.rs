@@ -1,9 +1,16 @@
 use core::mem;
 use core::ptr;
 use core::usize;
+use core::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
 
 use crate::lock;
 use crate::vec;
+use crate::param;
+use crate::timer;
+use crate::gdt;
+use crate::vm;
+use crate::frame;
+use crate::intr;
 
 #[link(name = "uos")]
 extern {
@@ -16,17 +23,48 @@ extern {
 	fn syscall();
 }
 
+// task switch interrupt vector (the same as in arch.s)
+pub const SWITCH_TASK_VEC_NUM: usize = 48;
+
 const TASK_STACK_SIZE: usize = 0x1000;
 const STACK_PTR_MASK: u32 = !(TASK_STACK_SIZE - 1) as u32;
 
+// ring 3 interrupt frame (eip, cs, eflags, user esp, ss) located at the top of kernel stack
+const USER_INTR_FRAME_SIZE: u32 = 5 * mem::size_of::<u32>() as u32;
+
+// saved registers (ds and general purpose registers) located below interrupt frame
+const SAVED_REGS_SIZE: u32 = 9 * mem::size_of::<u32>() as u32;
+
+// time slice after which running task is preempted (0 disables preemption)
+static SCHED_SLICE_MS: param::Param<u32> = param!("sched.slice_ms", u32, 0);
+
+// time slice in timer ticks and current task time slice start tick
+static SLICE_TICKS: AtomicUsize = AtomicUsize::new(0);
+static SLICE_START: AtomicUsize = AtomicUsize::new(0);
+
 struct Task {
 	tid: usize,
+	// kernel stack top used on privilege level change (user mode tasks only)
+	kernel_stack: u32,
+	// page directory base address, tasks can share address space
+	pdbr: u32,
+	// parked task isn't scheduled until the flag is set
+	wake_flag: Option<&'static AtomicBool>,
 	cpu_state: TaskCpuState
 }
 
+impl Task {
+	fn is_runnable(&self) -> bool {
+		self.wake_flag.map_or(true, |f| f.load(Ordering::SeqCst))
+	}
+}
+
+// task state saved on the stack by switch_task, user_esp and ss are present
+// only if task was interrupted in user mode
 #[repr(C)]
 struct TaskCpuState {
-	// TODO add ds and pdbr (also u32 should be changed to usize)
+	// TODO u32 should be changed to usize
+	ds: u32,
 	edi: u32,
 	esi: u32,
 	ebp: u32,
@@ -37,10 +75,24 @@ struct TaskCpuState {
 	eax: u32,
 	eip: u32,
 	cs: u32,
-	eflags: u32
+	eflags: u32,
+	user_esp: u32,
+	ss: u32
 }
 
-static NULL_TASK_CPU_STATE: TaskCpuState = TaskCpuState { edi: 0, esi: 0, ebp: 0, esp: 0, ebx: 0, edx: 0, ecx: 0, eax: 0, eip: 0, cs: 0, eflags: 0 };
+impl TaskCpuState {
+	// saved state size depends on privilege level of the interrupted code
+	fn size(&self) -> usize {
+		if self.cs & 0x3 != 0 {
+			mem::size_of::<TaskCpuState>()
+		} else {
+			mem::size_of::<TaskCpuState>() - 2 * mem::size_of::<u32>()
+		}
+	}
+}
+
+static NULL_TASK_CPU_STATE: TaskCpuState = TaskCpuState { ds: 0, edi: 0, esi: 0, ebp: 0, esp: 0, ebx: 0, edx: 0, ecx: 0, eax: 0, eip: 0, cs: 0, eflags: 0,
+	user_esp: 0, ss: 0 };
 
 type TaskQueue = (Option<Task>, vec::Vec<Task>);
 
@@ -50,6 +102,9 @@ pub fn init_curr_task(ttid: usize) {
 	unsafe {
 		let mut cur_task = Task {
 			tid: ttid,
+			kernel_stack: 0,
+			pdbr: vm::curr_pdbr(),
+			wake_flag: None,
 			cpu_state: TaskCpuState {
 				..NULL_TASK_CPU_STATE
 			}
@@ -67,13 +122,19 @@ pub fn init_curr_task(ttid: usize) {
 
 		*curr_task = Some(cur_task);
 	}
+
+	SLICE_TICKS.store(timer::ms_to_ticks(SCHED_SLICE_MS.get()), Ordering::SeqCst);
 }
 
 fn get_max_tid() -> usize {
 	let mut max_tid: usize = 0;
 
 	let tasks_guard = TASKS.lock();
-	let (_, tasks) = &*tasks_guard;
+	let (curr_task, tasks) = &*tasks_guard;
+
+	if let Some(cur_task) = curr_task {
+		max_tid = cur_task.tid;
+	}
 	
 	for t in tasks.iter() {
 		if t.tid > max_tid {
@@ -110,6 +171,9 @@ pub fn create(f: fn()) {
 	unsafe {
 		let mut new_task = Task {
 			tid: get_max_tid() + 1,
+			kernel_stack: 0,
+			pdbr: vm::kernel_pdbr(),
+			wake_flag: None,
 			cpu_state: TaskCpuState {
 				..NULL_TASK_CPU_STATE
 			}
@@ -117,7 +181,8 @@ pub fn create(f: fn()) {
 
 		let new_task_state = &mut new_task.cpu_state;
 
-		new_task_state.eip = task_wrapper as u32;
+		new_task_state.ds = gdt::KERNEL_DATA_SEL as u32;
+		new_task_state.eip = task_wrapper as *const () as u32;
 		new_task_state.esp = get_stack_ptr();
 
 		// placing task body function at the top of  the stack
@@ -137,12 +202,139 @@ pub fn create(f: fn()) {
 	}
 }
 
+// creates ring 3 task running in the specified address space, stack page is mapped to the end of
+// it's user part, entry point code should be accessible from user mode
+pub fn create_user(entry: usize, pdbr: u32) -> Option<usize> {
+	let stack_page = vm::USER_SPACE_END - vm::PAGE_SIZE;
+
+	let frame = frame::alloc()?;
+
+	if !vm::map_page(pdbr, stack_page, frame, true) {
+		frame::free(frame);
+		return None
+	}
+
+	Some(create_user_with_stack(entry, pdbr, vm::USER_SPACE_END - mem::size_of::<u32>()))
+}
+
+// creates ring 3 task which stack is already mapped to it's address space
+pub fn create_user_with_stack(entry: usize, pdbr: u32, user_esp: usize) -> usize {
+	unsafe {
+		let kernel_stack_page = get_stack_ptr() & STACK_PTR_MASK;
+		let kernel_stack_top = kernel_stack_page + TASK_STACK_SIZE as u32;
+
+		let mut new_task = Task {
+			tid: get_max_tid() + 1,
+			kernel_stack: kernel_stack_top,
+			pdbr,
+			wake_flag: None,
+			cpu_state: TaskCpuState {
+				..NULL_TASK_CPU_STATE
+			}
+		};
+
+		let new_task_state = &mut new_task.cpu_state;
+
+		new_task_state.ds = gdt::USER_DATA_SEL as u32;
+		new_task_state.eip = entry as u32;
+		new_task_state.cs = gdt::USER_CODE_SEL as u32;
+		new_task_state.eflags = get_eflags();
+		new_task_state.user_esp = user_esp as u32;
+		new_task_state.ss = gdt::USER_DATA_SEL as u32;
+
+		// pointing to the interrupt frame created on privilege level change
+		new_task_state.esp = kernel_stack_top - USER_INTR_FRAME_SIZE;
+
+		let new_tid = new_task.tid;
+
+		let mut tasks_guard = TASKS.lock();
+		let (_, tasks) = &mut *tasks_guard;
+
+		tasks.push(new_task);
+
+		new_tid
+	}
+}
+
 pub fn suspend() {
 	unsafe {
 		syscall();
 	}
 }
 
+// should be called from timer interrupt handler after EOI
+pub fn preempt() {
+	let slice_ticks = SLICE_TICKS.load(Ordering::SeqCst);
+	if slice_ticks == 0 {
+		return
+	}
+
+	if timer::ticks() - SLICE_START.load(Ordering::SeqCst) < slice_ticks {
+		return
+	}
+
+	// task switch will deadlock if interrupted task holds task queue lock
+	if !TASKS.is_locked() {
+		suspend();
+	}
+}
+
+// blocks current task until the flag is set, flag can be set from interrupt handler
+pub fn park(flag: &'static AtomicBool) {
+	set_wake_flag(Some(flag));
+
+	// scheduler can still return to this task if there are no other runnable tasks
+	while !flag.load(Ordering::SeqCst) {
+		suspend();
+	}
+
+	set_wake_flag(None);
+}
+
+fn set_wake_flag(flag: Option<&'static AtomicBool>) {
+	let mut tasks_guard = TASKS.lock();
+	let (curr_task, _) = &mut *tasks_guard;
+
+	if let Some(cur_task) = curr_task {
+		cur_task.wake_flag = flag;
+	}
+}
+
+// suspends current task at least for specified amount of time
+pub fn sleep(ms: u32) {
+	let wake_up_tick = timer::ticks() + timer::ms_to_ticks(ms);
+
+	while timer::ticks() < wake_up_tick {
+		suspend();
+	}
+}
+
+// terminates current task
+pub fn exit() -> ! {
+	release_addr_space();
+
+	reset_curr_task();
+
+	suspend();
+
+	// completed task execution should never be resumed
+	console_println!("error: completed task resumed");
+
+	loop {}
+}
+
+// current task id without waiting for task queue lock (None if queue is locked)
+pub fn try_curr_task_id() -> Option<usize> {
+	if TASKS.is_locked() {
+		return None
+	}
+
+	let tasks_guard = TASKS.lock();
+	let (curr_task, _) = &*tasks_guard;
+
+	curr_task.as_ref().map(|ct| ct.tid)
+}
+
 pub fn curr_task_id() -> usize {
 	let tasks_guard = TASKS.lock();
 	let (curr_task, _) = &*tasks_guard;
@@ -159,14 +351,30 @@ pub fn curr_task_id() -> usize {
 fn task_wrapper(task_fn: fn()) {
 	task_fn();
 
-	reset_curr_task();
+	exit();
+}
 
-	suspend();
+// destroys current task address space unless it's shared with other tasks
+fn release_addr_space() {
+	let mut tasks_guard = TASKS.lock();
+	let (curr_task, tasks) = &mut *tasks_guard;
 
-	// completed task execution should never be resumed
-	console_println!("error: completed task resumed");
+	if let Some(cur_task) = curr_task {
+		let pdbr = cur_task.pdbr;
 
-	loop {}
+		if pdbr == vm::kernel_pdbr() || tasks.iter().any(|t| t.pdbr == pdbr) {
+			return
+		}
+
+		// task queue lock prevents preemption while address space is switched
+		cur_task.pdbr = vm::kernel_pdbr();
+
+		unsafe {
+			vm::switch_addr_space(vm::kernel_pdbr());
+		}
+
+		vm::destroy_addr_space(pdbr);
+	}
 }
 
 fn reset_curr_task() {
@@ -178,30 +386,39 @@ fn reset_curr_task() {
 
 #[no_mangle]
 pub unsafe extern fn switch_task_and_get_new_stack_ptr() -> *const u8 {
+	intr::count(SWITCH_TASK_VEC_NUM);
+
 	let mut tasks_guard = TASKS.lock();
 	let (curr_task, tasks) = &mut *tasks_guard;
 
-	let task_queue_head = tasks.pop();
-	if let Some(next_task) = task_queue_head {
+	// starting new time slice
+	SLICE_START.store(timer::ticks(), Ordering::SeqCst);
 
-		if let Some(cur_task) = curr_task {
+	// parked tasks are skipped (they keep their place in the queue)
+	let next_task_idx = tasks.iter().position(|t| t.is_runnable());
+	if let Some(next_task) = next_task_idx.and_then(|i| tasks.remove(i)) {
+
+		if let Some(cur_task) = curr_task.take() {
 			// placing current task to the end of the task queue
-			tasks.push(Task {
-				tid: cur_task.tid,
-				cpu_state: TaskCpuState {
-					..cur_task.cpu_state
-				}
-			});
+			tasks.push(cur_task);
 		}
 
 		let next_task_esp = next_task.cpu_state.esp;
 
+		// user mode task should get it's own kernel stack on interrupt
+		if next_task.kernel_stack != 0 {
+			gdt::set_kernel_stack(next_task.kernel_stack as usize);
+		}
+
+		// kernel stacks are located in the shared part of address space
+		vm::switch_addr_space(next_task.pdbr);
+
 		*curr_task = Some(next_task);
 
-		(next_task_esp - 32) as *const u8
+		(next_task_esp - SAVED_REGS_SIZE) as *const u8
 	} else {
 		if let Some(cur_task) = curr_task {
-			let rv = (cur_task.cpu_state.esp - 32) as *const u8;
+			let rv = (cur_task.cpu_state.esp - SAVED_REGS_SIZE) as *const u8;
 
 			rv
 		} else {
@@ -218,7 +435,10 @@ pub unsafe extern fn save_current_task_state(task_cpu_state_ptr: *const u8) {
 	let (curr_task, _) = &mut *tasks_guard;
 
 	if let Some(cur_task) = curr_task {
-		ptr::copy_nonoverlapping(task_cpu_state_ptr as *const TaskCpuState, &mut cur_task.cpu_state, 1);
+		let saved_state = task_cpu_state_ptr as *const TaskCpuState;
+
+		let state_size = (*saved_state).size();
+		ptr::copy_nonoverlapping(task_cpu_state_ptr, &mut cur_task.cpu_state as *mut TaskCpuState as *mut u8, state_size);
 	}
 }
 
@@ -228,7 +448,9 @@ pub unsafe extern fn restore_current_task_state(task_cpu_state_ptr: *mut u8) {
 	let (curr_task, _) = &*tasks_guard;
 
 	if let Some(cur_task) = curr_task {
-		ptr::copy_nonoverlapping(&cur_task.cpu_state, task_cpu_state_ptr as *mut TaskCpuState, 1);
+		// kernel mode task stack contents above interrupt frame should stay intact
+		let state_size = cur_task.cpu_state.size();
+		ptr::copy_nonoverlapping(&cur_task.cpu_state as *const TaskCpuState as *const u8, task_cpu_state_ptr, state_size);
 	} else {
 		console_println!("current task not set - failed to restore task state");
 
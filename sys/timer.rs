@@ -0,0 +1,36 @@
+use core::sync::atomic::{ AtomicUsize, Ordering };
+
+use crate::pio;
+
+const PIT_FREQ: u32 = 1193182;
+
+const PIT_CH0_IOPORT_NUM: u32 = 0x40;
+const PIT_CMD_IOPORT_NUM: u32 = 0x43;
+
+// channel 0, low/high byte access, square wave generator mode
+const PIT_CH0_SQUARE_WAVE_CMD: u32 = 0x36;
+
+pub const TICK_HZ: u32 = 1000;
+
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+pub unsafe fn init() {
+	let divisor = PIT_FREQ / TICK_HZ;
+
+	pio::out_byte(PIT_CH0_SQUARE_WAVE_CMD, PIT_CMD_IOPORT_NUM);
+	pio::out_byte(divisor & 0xff, PIT_CH0_IOPORT_NUM);
+	pio::out_byte(divisor >> 8, PIT_CH0_IOPORT_NUM);
+}
+
+// should be called from timer interrupt handler only
+pub fn tick() {
+	TICKS.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn ticks() -> usize {
+	TICKS.load(Ordering::SeqCst)
+}
+
+pub fn ms_to_ticks(ms: u32) -> usize {
+	(ms as u64 * TICK_HZ as u64 / 1000) as usize
+}
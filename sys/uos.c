@@ -38,3 +38,21 @@ int memcmp(const void* s1, const void* s2, size_t n) {
 int bcmp(const void* s1, const void* s2, size_t n) {
 	return memcmp(s1, s2, n);
 }
+
+// regions can overlap
+void* memmove(void* dst, const void* src, size_t n) {
+	char* d = dst;
+	const char* s = src;
+
+	if (d < s) {
+		for (size_t i = 0;i < n;i++) {
+			d[i] = s[i];
+		}
+	} else {
+		for (size_t i = n;i > 0;i--) {
+			d[i - 1] = s[i - 1];
+		}
+	}
+
+	return dst;
+}
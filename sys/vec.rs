@@ -101,6 +101,43 @@ impl<T> Vec<T> {
 		self.pop()
 	}
 
+	// shifts elements starting from i to the right
+	pub fn insert(&mut self, i: usize, val: T) {
+		if i > self.len {
+			return ()
+		}
+
+		self.push(val);
+
+		let val_ptr = self.buf.wrapping_add(i);
+
+		unsafe {
+			let val = ptr::read(self.buf.wrapping_add(self.len - 1));
+
+			ptr::copy(val_ptr, val_ptr.wrapping_add(1), self.len - i - 1);
+			ptr::write(val_ptr, val);
+		}
+	}
+
+	// preserves elements order, unlike swap_remove
+	pub fn remove(&mut self, i: usize) -> Option<T> {
+		if i >= self.len {
+			return None
+		}
+
+		let val_ptr = self.buf.wrapping_add(i);
+
+		unsafe {
+			let val = ptr::read(val_ptr);
+
+			// shifting the rest of elements to the left
+			ptr::copy(val_ptr.wrapping_add(1), val_ptr, self.len - i - 1);
+			self.len -= 1;
+
+			Some(val)
+		}
+	}
+
 	pub fn pop(&mut self) -> Option<T> {
 		if self.len == 0 {
 			None
@@ -149,6 +186,9 @@ impl<T> ops::DerefMut for Vec<T> {
 
 impl<T> Drop for Vec<T> {
 	fn drop(&mut self) {
-		alloc::dealloc(self.buf as *mut u8);
+		// buffer is not allocated for empty vector created by new
+		if !self.buf.is_null() {
+			alloc::dealloc(self.buf as *mut u8);
+		}
 	}
 }
@@ -0,0 +1,353 @@
+use core::ptr;
+
+use crate::frame;
+
+#[link(name = "uos")]
+extern {
+	fn get_cr3() -> u32;
+
+	fn set_cr3(pdbr: u32);
+
+	fn invalidate_page(addr: u32);
+
+	// user mode accessible part of system image boundaries (defined by linker script)
+	static __user_start: u8;
+
+	static __user_end: u8;
+}
+
+pub const PAGE_SIZE: usize = 0x1000;
+
+const PG_TBL_ENTRY_PRESENT_BIT: u32 = 0x1;
+const PG_TBL_ENTRY_RW_BIT: u32 = 0x2;
+const PG_TBL_ENTRY_USER_BIT: u32 = 0x4;
+const PG_TBL_ENTRY_WRITE_THROUGH_BIT: u32 = 0x8;
+const PG_TBL_ENTRY_CACHE_DISABLE_BIT: u32 = 0x10;
+const PG_TBL_ENTRY_ADDR_MASK: u32 = !(PAGE_SIZE as u32 - 1);
+
+const PG_TBL_ENTRIES: usize = 1024;
+
+// user part of the address space, all other page directory entries are shared by address spaces
+pub const USER_SPACE_START: usize = 0x400000;
+pub const USER_SPACE_END: usize = 0xc0000000;
+
+// user heap area, program break is the end of contiguously mapped heap pages
+pub const USER_HEAP_START: usize = 0x40000000;
+pub const USER_HEAP_END: usize = 0x70000000;
+
+const USER_PD_ENTRIES_START: usize = USER_SPACE_START >> 22;
+const USER_PD_ENTRIES_END: usize = USER_SPACE_END >> 22;
+
+// page directory used during system initialization and by kernel tasks
+static mut KERNEL_PDBR: u32 = 0;
+
+pub unsafe fn init() {
+	KERNEL_PDBR = get_cr3() & PG_TBL_ENTRY_ADDR_MASK;
+
+	let user_start = &__user_start as *const u8 as usize;
+	let user_end = &__user_end as *const u8 as usize;
+
+	set_user_access(user_start, user_end - user_start);
+}
+
+// makes already mapped memory area accessible from user mode
+// (page directory and page tables should be identity mapped)
+unsafe fn set_user_access(addr: usize, size: usize) {
+	let pg_dir = (get_cr3() & PG_TBL_ENTRY_ADDR_MASK) as *mut u32;
+
+	let mut pg_addr = addr & !(PAGE_SIZE - 1);
+	while pg_addr < addr + size {
+		let pd_entry = pg_dir.wrapping_add(pg_addr >> 22);
+		*pd_entry |= PG_TBL_ENTRY_USER_BIT;
+
+		let pg_tbl = (*pd_entry & PG_TBL_ENTRY_ADDR_MASK) as *mut u32;
+
+		let pt_entry = pg_tbl.wrapping_add((pg_addr >> 12) & (PG_TBL_ENTRIES - 1));
+		*pt_entry |= PG_TBL_ENTRY_USER_BIT;
+
+		invalidate_page(pg_addr as u32);
+
+		pg_addr += PAGE_SIZE;
+	}
+}
+
+// checks that memory area is mapped and accessible from user mode
+pub fn is_user_accessible(addr: usize, size: usize, write: bool) -> bool {
+	let end_addr = match addr.checked_add(size) {
+		Some(ea) => ea,
+		_ => return false
+	};
+
+	let mut required_bits = PG_TBL_ENTRY_PRESENT_BIT | PG_TBL_ENTRY_USER_BIT;
+	if write {
+		required_bits |= PG_TBL_ENTRY_RW_BIT;
+	}
+
+	unsafe {
+		let pg_dir = (get_cr3() & PG_TBL_ENTRY_ADDR_MASK) as *const u32;
+
+		let mut pg_addr = addr & !(PAGE_SIZE - 1);
+		while pg_addr < end_addr {
+			let pd_entry = *pg_dir.wrapping_add(pg_addr >> 22);
+			if pd_entry & required_bits != required_bits {
+				return false
+			}
+
+			let pg_tbl = (pd_entry & PG_TBL_ENTRY_ADDR_MASK) as *const u32;
+
+			let pt_entry = *pg_tbl.wrapping_add((pg_addr >> 12) & (PG_TBL_ENTRIES - 1));
+			if pt_entry & required_bits != required_bits {
+				return false
+			}
+
+			pg_addr += PAGE_SIZE;
+		}
+	}
+
+	true
+}
+
+pub fn kernel_pdbr() -> u32 {
+	unsafe {
+		KERNEL_PDBR
+	}
+}
+
+pub fn curr_pdbr() -> u32 {
+	unsafe {
+		get_cr3() & PG_TBL_ENTRY_ADDR_MASK
+	}
+}
+
+// loads page directory only if it differs from the current one (avoiding TLB flush)
+pub unsafe fn switch_addr_space(pdbr: u32) {
+	if pdbr != curr_pdbr() {
+		set_cr3(pdbr);
+	}
+}
+
+// identity maps device memory page to the kernel part of address space (caching is disabled),
+// should be called before user address spaces creation (kernel page directory entries are copied)
+pub fn map_mmio(addr: usize) -> bool {
+	if addr >= USER_SPACE_START && addr < USER_SPACE_END {
+		return false
+	}
+
+	unsafe {
+		let pd_entry = (KERNEL_PDBR as *mut u32).add(addr >> 22);
+
+		if *pd_entry & PG_TBL_ENTRY_PRESENT_BIT == 0 {
+			let pg_tbl = match frame::alloc() {
+				Some(pt) => pt as u32,
+				_ => return false
+			};
+
+			*pd_entry = pg_tbl | PG_TBL_ENTRY_PRESENT_BIT | PG_TBL_ENTRY_RW_BIT;
+		}
+
+		let pg_tbl = (*pd_entry & PG_TBL_ENTRY_ADDR_MASK) as *mut u32;
+		*pg_tbl.add((addr >> 12) & (PG_TBL_ENTRIES - 1)) = (addr as u32 & PG_TBL_ENTRY_ADDR_MASK) | PG_TBL_ENTRY_PRESENT_BIT | PG_TBL_ENTRY_RW_BIT
+			| PG_TBL_ENTRY_WRITE_THROUGH_BIT | PG_TBL_ENTRY_CACHE_DISABLE_BIT;
+
+		invalidate_page(addr as u32);
+	}
+
+	true
+}
+
+// creates address space with empty user part
+pub fn create_addr_space() -> Option<u32> {
+	let pdbr = frame::alloc()? as u32;
+
+	unsafe {
+		let kernel_pg_dir = KERNEL_PDBR as *const u32;
+		let pg_dir = pdbr as *mut u32;
+
+		for i in (0..USER_PD_ENTRIES_START).chain(USER_PD_ENTRIES_END..PG_TBL_ENTRIES) {
+			*pg_dir.add(i) = *kernel_pg_dir.add(i);
+		}
+	}
+
+	Some(pdbr)
+}
+
+// maps page frame to the user part of the address space
+pub fn map_page(pdbr: u32, addr: usize, frame: usize, write: bool) -> bool {
+	if addr < USER_SPACE_START || addr >= USER_SPACE_END {
+		return false
+	}
+
+	let mut flags = PG_TBL_ENTRY_PRESENT_BIT | PG_TBL_ENTRY_USER_BIT;
+	if write {
+		flags |= PG_TBL_ENTRY_RW_BIT;
+	}
+
+	unsafe {
+		let pd_entry = (pdbr as *mut u32).add(addr >> 22);
+
+		if *pd_entry & PG_TBL_ENTRY_PRESENT_BIT == 0 {
+			let pg_tbl = match frame::alloc() {
+				Some(pt) => pt as u32,
+				_ => return false
+			};
+
+			// access rights are checked on the page table entries level
+			*pd_entry = pg_tbl | PG_TBL_ENTRY_PRESENT_BIT | PG_TBL_ENTRY_RW_BIT | PG_TBL_ENTRY_USER_BIT;
+		}
+
+		let pg_tbl = (*pd_entry & PG_TBL_ENTRY_ADDR_MASK) as *mut u32;
+		*pg_tbl.add((addr >> 12) & (PG_TBL_ENTRIES - 1)) = frame as u32 | flags;
+
+		if pdbr == curr_pdbr() {
+			invalidate_page(addr as u32);
+		}
+	}
+
+	true
+}
+
+// creates a copy of the address space, user part pages are copied
+pub fn clone_addr_space(src_pdbr: u32) -> Option<u32> {
+	let pdbr = create_addr_space()?;
+
+	unsafe {
+		let src_pg_dir = src_pdbr as *const u32;
+
+		for i in USER_PD_ENTRIES_START..USER_PD_ENTRIES_END {
+			let pd_entry = *src_pg_dir.add(i);
+			if pd_entry & PG_TBL_ENTRY_PRESENT_BIT == 0 {
+				continue
+			}
+
+			let src_pg_tbl = (pd_entry & PG_TBL_ENTRY_ADDR_MASK) as *const u32;
+
+			for j in 0..PG_TBL_ENTRIES {
+				let pt_entry = *src_pg_tbl.add(j);
+				if pt_entry & PG_TBL_ENTRY_PRESENT_BIT == 0 {
+					continue
+				}
+
+				let frame = match frame::alloc() {
+					Some(f) => f,
+					_ => {
+						destroy_addr_space(pdbr);
+						return None
+					}
+				};
+
+				ptr::copy_nonoverlapping((pt_entry & PG_TBL_ENTRY_ADDR_MASK) as *const u8, frame as *mut u8, PAGE_SIZE);
+
+				let addr = (i << 22) | (j << 12);
+				if !map_page(pdbr, addr, frame, pt_entry & PG_TBL_ENTRY_RW_BIT != 0) {
+					frame::free(frame);
+					destroy_addr_space(pdbr);
+					return None
+				}
+			}
+		}
+	}
+
+	Some(pdbr)
+}
+
+// releases user part pages, page tables and page directory
+// (address space should not be active)
+pub fn destroy_addr_space(pdbr: u32) {
+	if pdbr == kernel_pdbr() || pdbr == curr_pdbr() {
+		console_println!("attempt to destroy active address space: {:#x}", pdbr);
+
+		return
+	}
+
+	unsafe {
+		let pg_dir = pdbr as *const u32;
+
+		for i in USER_PD_ENTRIES_START..USER_PD_ENTRIES_END {
+			let pd_entry = *pg_dir.add(i);
+			if pd_entry & PG_TBL_ENTRY_PRESENT_BIT == 0 {
+				continue
+			}
+
+			let pg_tbl = (pd_entry & PG_TBL_ENTRY_ADDR_MASK) as *const u32;
+
+			for j in 0..PG_TBL_ENTRIES {
+				let pt_entry = *pg_tbl.add(j);
+				if pt_entry & PG_TBL_ENTRY_PRESENT_BIT != 0 {
+					frame::free((pt_entry & PG_TBL_ENTRY_ADDR_MASK) as usize);
+				}
+			}
+
+			frame::free(pg_tbl as usize);
+		}
+	}
+
+	frame::free(pdbr as usize);
+}
+
+// returns physical address mapped to the user part virtual address
+pub fn translate(pdbr: u32, addr: usize) -> Option<usize> {
+	if addr < USER_SPACE_START || addr >= USER_SPACE_END {
+		return None
+	}
+
+	lookup(pdbr, addr)
+}
+
+// returns physical address mapped to the kernel part virtual address
+pub fn kernel_translate(addr: usize) -> Option<usize> {
+	if addr >= USER_SPACE_START && addr < USER_SPACE_END {
+		return None
+	}
+
+	lookup(curr_pdbr(), addr)
+}
+
+// page directory and page tables should be identity mapped
+fn lookup(pdbr: u32, addr: usize) -> Option<usize> {
+	unsafe {
+		let pd_entry = *(pdbr as *const u32).add(addr >> 22);
+		if pd_entry & PG_TBL_ENTRY_PRESENT_BIT == 0 {
+			return None
+		}
+
+		let pg_tbl = (pd_entry & PG_TBL_ENTRY_ADDR_MASK) as *const u32;
+
+		let pt_entry = *pg_tbl.add((addr >> 12) & (PG_TBL_ENTRIES - 1));
+		if pt_entry & PG_TBL_ENTRY_PRESENT_BIT == 0 {
+			return None
+		}
+
+		Some((pt_entry & PG_TBL_ENTRY_ADDR_MASK) as usize | (addr & (PAGE_SIZE - 1)))
+	}
+}
+
+// returns current program break
+pub fn heap_end(pdbr: u32) -> usize {
+	let mut end = USER_HEAP_START;
+	while end < USER_HEAP_END && translate(pdbr, end).is_some() {
+		end += PAGE_SIZE;
+	}
+
+	end
+}
+
+// maps heap pages up to specified address (heap never shrinks), returns new program break
+pub fn grow_heap(pdbr: u32, addr: usize) -> usize {
+	let mut end = heap_end(pdbr);
+
+	while end < addr && end < USER_HEAP_END {
+		let frame = match frame::alloc() {
+			Some(f) => f,
+			_ => break
+		};
+
+		if !map_page(pdbr, end, frame, true) {
+			frame::free(frame);
+			break;
+		}
+
+		end += PAGE_SIZE;
+	}
+
+	end
+}
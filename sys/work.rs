@@ -0,0 +1,102 @@
+use core::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+
+use crate::intr;
+use crate::task;
+
+// deferred work item, function is called by worker task with interrupts enabled
+#[derive(Clone, Copy)]
+struct Work {
+	func: fn(usize),
+	arg: usize
+}
+
+const WORK_QUEUE_SIZE: usize = 64;
+
+fn nop_work(_: usize) {}
+
+const NOP_WORK: Work = Work { func: nop_work, arg: 0 };
+
+// queue is modified with interrupts disabled only (items are queued by interrupt handlers),
+// fixed size queue is used because heap allocator can't be used in interrupt context
+static mut WORK_QUEUE: [Work; WORK_QUEUE_SIZE] = [NOP_WORK; WORK_QUEUE_SIZE];
+
+static mut QUEUE_HEAD: usize = 0;
+static mut QUEUE_LEN: usize = 0;
+
+// set when work is queued, worker task is parked while it's clear
+static WORK_READY: AtomicBool = AtomicBool::new(false);
+
+// items dropped due to queue overflow
+static DROPPED_CNT: AtomicUsize = AtomicUsize::new(0);
+
+// queues function call to be made by worker task, can be called from interrupt handler,
+// returns false if queue is full
+pub fn schedule(func: fn(usize), arg: usize) -> bool {
+	let intr_enabled = intr::save_and_disable();
+
+	let queued = unsafe {
+		if QUEUE_LEN < WORK_QUEUE_SIZE {
+			WORK_QUEUE[(QUEUE_HEAD + QUEUE_LEN) % WORK_QUEUE_SIZE] = Work { func, arg };
+			QUEUE_LEN += 1;
+
+			true
+		} else {
+			false
+		}
+	};
+
+	intr::restore(intr_enabled);
+
+	if queued {
+		WORK_READY.store(true, Ordering::SeqCst);
+	} else {
+		DROPPED_CNT.fetch_add(1, Ordering::Relaxed);
+	}
+
+	queued
+}
+
+pub fn pending_cnt() -> usize {
+	unsafe {
+		QUEUE_LEN
+	}
+}
+
+pub fn dropped_cnt() -> usize {
+	DROPPED_CNT.load(Ordering::Relaxed)
+}
+
+fn next() -> Option<Work> {
+	let intr_enabled = intr::save_and_disable();
+
+	let work = unsafe {
+		if QUEUE_LEN > 0 {
+			let w = WORK_QUEUE[QUEUE_HEAD];
+
+			QUEUE_HEAD = (QUEUE_HEAD + 1) % WORK_QUEUE_SIZE;
+			QUEUE_LEN -= 1;
+
+			Some(w)
+		} else {
+			None
+		}
+	};
+
+	intr::restore(intr_enabled);
+
+	work
+}
+
+// kernel task body, work items can block (i.e. take lock::Mutex) since they run in task context
+pub fn worker() {
+	loop {
+		// flag is cleared before the queue is drained, so work queued afterwards wakes worker up
+		WORK_READY.store(false, Ordering::SeqCst);
+
+		while let Some(w) = next() {
+			(w.func)(w.arg);
+		}
+
+		task::park(&WORK_READY);
+	}
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate libuos;
+
+use libuos::env;
+use libuos::heap;
+use libuos::syscall;
+
+// sample user program, started using 'run hello' shell command
+#[no_mangle]
+pub extern fn main() -> i32 {
+	println!("hello from task {}", syscall::getpid());
+
+	for (i, arg) in env::args().enumerate() {
+		println!("argv[{}] = {}", i, arg);
+	}
+
+	let buf = heap::alloc(64);
+	if buf.is_null() {
+		println!("heap allocation failed");
+
+		return 1
+	}
+
+	heap::dealloc(buf);
+
+	0
+}
@@ -0,0 +1,48 @@
+use core::slice;
+use core::str;
+
+// arguments passed by the system on program start
+static mut ARGC: usize = 0;
+static mut ARGV: *const *const u8 = 0 as *const *const u8;
+
+pub unsafe fn init(argc: usize, argv: *const *const u8) {
+	ARGC = argc;
+	ARGV = argv;
+}
+
+pub struct Args {
+	pos: usize
+}
+
+impl Iterator for Args {
+	type Item = &'static str;
+
+	fn next(&mut self) -> Option<&'static str> {
+		unsafe {
+			if self.pos >= ARGC {
+				return None
+			}
+
+			let arg = *ARGV.add(self.pos);
+			self.pos += 1;
+
+			let mut len = 0;
+			while *arg.add(len) != 0 {
+				len += 1;
+			}
+
+			str::from_utf8(slice::from_raw_parts(arg, len)).ok()
+		}
+	}
+}
+
+// program arguments, the first one is the program name
+pub fn args() -> Args {
+	Args { pos: 0 }
+}
+
+pub fn args_cnt() -> usize {
+	unsafe {
+		ARGC
+	}
+}
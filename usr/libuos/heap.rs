@@ -0,0 +1,128 @@
+use core::mem;
+use core::ptr;
+
+use crate::syscall;
+
+// minimal heap extension size in blocks
+const MIN_GROW_BLOCKS: usize = 512;
+
+// free list allocator (similar to sys/alloc.rs) which memory is obtained by brk system call,
+// program is assumed to be single threaded
+struct MemBlock {
+	next: *mut MemBlock,
+	size: usize
+}
+
+static mut BASE: MemBlock = MemBlock { next: 0 as *mut MemBlock, size: 0 };
+
+static mut FREE_BLOCK: *mut MemBlock = 0 as *mut MemBlock;
+
+// the end of memory managed by allocator
+static mut HEAP_END: usize = 0;
+
+pub fn alloc(size: usize) -> *mut u8 {
+	unsafe {
+		if FREE_BLOCK.is_null() {
+			BASE.next = ptr::addr_of_mut!(BASE);
+			FREE_BLOCK = ptr::addr_of_mut!(BASE);
+		}
+
+		// requested size in blocks plus header block
+		let size_blocks = (size + mem::size_of::<MemBlock>() - 1) / mem::size_of::<MemBlock>() + 1;
+
+		let mut prev_blk = FREE_BLOCK;
+		let mut curr_blk = (*prev_blk).next;
+
+		loop {
+			if (*curr_blk).size >= size_blocks {
+				if (*curr_blk).size == size_blocks {
+					(*prev_blk).next = (*curr_blk).next;
+				} else {
+					// allocating the tail of the free block
+					(*curr_blk).size -= size_blocks;
+					curr_blk = curr_blk.add((*curr_blk).size);
+					(*curr_blk).size = size_blocks;
+				}
+
+				FREE_BLOCK = prev_blk;
+
+				return curr_blk.add(1) as *mut u8
+			}
+
+			if curr_blk == FREE_BLOCK {
+				// list wrapped around
+				curr_blk = grow(size_blocks);
+				if curr_blk.is_null() {
+					return ptr::null_mut()
+				}
+			}
+
+			prev_blk = curr_blk;
+			curr_blk = (*curr_blk).next;
+		}
+	}
+}
+
+pub fn dealloc(ptr: *mut u8) {
+	if ptr.is_null() {
+		return
+	}
+
+	unsafe {
+		let dealloc_blk = (ptr as *mut MemBlock).sub(1);
+
+		// looking for the free list position (list is sorted by address)
+		let mut curr_blk = FREE_BLOCK;
+		while !(dealloc_blk > curr_blk && dealloc_blk < (*curr_blk).next) {
+			if curr_blk >= (*curr_blk).next && (dealloc_blk > curr_blk || dealloc_blk < (*curr_blk).next) {
+				// block is located at the start or at the end of the list
+				break;
+			}
+
+			curr_blk = (*curr_blk).next;
+		}
+
+		let next_blk = (*curr_blk).next;
+
+		// merging with the upper neighbour
+		if dealloc_blk.add((*dealloc_blk).size) == next_blk {
+			(*dealloc_blk).size += (*next_blk).size;
+			(*dealloc_blk).next = (*next_blk).next;
+		} else {
+			(*dealloc_blk).next = next_blk;
+		}
+
+		// merging with the lower neighbour
+		if curr_blk.add((*curr_blk).size) == dealloc_blk {
+			(*curr_blk).size += (*dealloc_blk).size;
+			(*curr_blk).next = (*dealloc_blk).next;
+		} else {
+			(*curr_blk).next = dealloc_blk;
+		}
+
+		FREE_BLOCK = curr_blk;
+	}
+}
+
+// extends heap and places new memory to the free list
+unsafe fn grow(size_blocks: usize) -> *mut MemBlock {
+	let grow_blocks = if size_blocks < MIN_GROW_BLOCKS { MIN_GROW_BLOCKS } else { size_blocks };
+
+	if HEAP_END == 0 {
+		HEAP_END = syscall::brk(0);
+	}
+
+	let new_end = syscall::brk(HEAP_END + grow_blocks * mem::size_of::<MemBlock>());
+	if new_end < HEAP_END + grow_blocks * mem::size_of::<MemBlock>() {
+		return ptr::null_mut()
+	}
+
+	let new_blk = HEAP_END as *mut MemBlock;
+	(*new_blk).size = (new_end - HEAP_END) / mem::size_of::<MemBlock>();
+
+	HEAP_END = new_end;
+
+	dealloc(new_blk.add(1) as *mut u8);
+
+	FREE_BLOCK
+}
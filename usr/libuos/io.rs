@@ -0,0 +1,46 @@
+use core::fmt;
+
+use crate::syscall;
+
+// formatted output goes directly to write system call (there is no buffering)
+struct StdoutWriter;
+
+impl fmt::Write for StdoutWriter {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		if syscall::write(syscall::STDOUT_FD, s.as_bytes()) < 0 {
+			Err(fmt::Error)
+		} else {
+			Ok(())
+		}
+	}
+}
+
+pub fn print(args: fmt::Arguments) {
+	let mut writer = StdoutWriter;
+
+	let _ = fmt::write(&mut writer, args);
+}
+
+pub fn print_str(s: &str) {
+	syscall::write(syscall::STDOUT_FD, s.as_bytes());
+}
+
+// reads line into the buffer (without new line character), returns line length
+pub fn read_line(buf: &mut [u8]) -> usize {
+	let mut len = 0;
+
+	while len < buf.len() {
+		let rv = syscall::read(syscall::STDIN_FD, &mut buf[len..len + 1]);
+		if rv <= 0 {
+			break;
+		}
+
+		if buf[len] == b'\n' {
+			break;
+		}
+
+		len += 1;
+	}
+
+	len
+}
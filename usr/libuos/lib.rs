@@ -0,0 +1,58 @@
+#![no_std]
+
+// user programs runtime library
+
+use core::panic::PanicInfo;
+
+#[macro_export]
+macro_rules! print {
+	( $f:expr ) => ( { $crate::io::print(format_args!($f)); } );
+	( $f:expr, $( $a:expr ), * ) => ( { $crate::io::print(format_args!($f, $( $a ), *)); } )
+}
+
+#[macro_export]
+macro_rules! println {
+	( $f:expr ) => ( { $crate::io::print(format_args!(concat!($f, "\n"))); } );
+	( $f:expr, $( $a:expr ), * ) => ( { $crate::io::print(format_args!(concat!($f, "\n"), $( $a ), *)); } )
+}
+
+pub mod syscall;
+
+pub mod io;
+
+pub mod env;
+
+pub mod heap;
+
+extern {
+	// program entry point defined by the program itself, returned value is the exit code
+	fn main() -> i32;
+}
+
+// the system calls this function with argc and argv arguments (see sys/elf.rs)
+#[no_mangle]
+pub unsafe extern fn _start(argc: usize, argv: *const *const u8) -> ! {
+	env::init(argc, argv);
+
+	let rv = main();
+
+	syscall::exit(rv);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+	if let Some(loc) = info.location() {
+		println!("panic at {}:{}", loc.file(), loc.line());
+	} else {
+		println!("panic");
+	}
+
+	syscall::exit(-1);
+}
+
+// the following functions make linker happy
+#[no_mangle]
+extern fn rust_eh_personality() {}
+
+#[no_mangle]
+extern fn _Unwind_Resume() {}
@@ -0,0 +1,59 @@
+typedef __SIZE_TYPE__ size_t;
+
+// memory functions required by compiler generated code
+
+void* memset(void* dst, int c, size_t n) {
+	char* d = dst;
+
+	for (size_t i = 0;i < n;i++) {
+		d[i] = c;
+	}
+
+	return dst;
+}
+
+void* memcpy(void* dst, const void* src, size_t n) {
+	char* d = dst;
+	const char* s = src;
+
+	for (size_t i = 0;i < n;i++) {
+		d[i] = s[i];
+	}
+
+	return dst;
+}
+
+// regions can overlap
+void* memmove(void* dst, const void* src, size_t n) {
+	char* d = dst;
+	const char* s = src;
+
+	if (d < s) {
+		for (size_t i = 0;i < n;i++) {
+			d[i] = s[i];
+		}
+	} else {
+		for (size_t i = n;i > 0;i--) {
+			d[i - 1] = s[i - 1];
+		}
+	}
+
+	return dst;
+}
+
+int memcmp(const void* s1, const void* s2, size_t n) {
+	const unsigned char* l = s1;
+	const unsigned char* r = s2;
+
+	for (size_t i = 0;i < n;i++) {
+		if (l[i] != r[i]) {
+			return l[i] < r[i] ? -1 : 1;
+		}
+	}
+
+	return 0;
+}
+
+int bcmp(const void* s1, const void* s2, size_t n) {
+	return memcmp(s1, s2, n);
+}
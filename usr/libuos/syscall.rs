@@ -0,0 +1,80 @@
+use core::mem;
+
+#[link(name = "uosrt")]
+extern {
+	fn syscall(num: usize, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> isize;
+}
+
+// system call numbers and error codes, the same as in sys/syscall.rs
+pub const SYS_YIELD: usize = 0;
+pub const SYS_EXIT: usize = 1;
+pub const SYS_SLEEP: usize = 2;
+pub const SYS_WRITE: usize = 3;
+pub const SYS_READ: usize = 4;
+pub const SYS_GETPID: usize = 5;
+pub const SYS_SPAWN: usize = 6;
+pub const SYS_BRK: usize = 7;
+
+pub const EBADF: isize = -9;
+pub const EFAULT: isize = -14;
+pub const EINVAL: isize = -22;
+pub const ENOSYS: isize = -38;
+
+pub const STDIN_FD: usize = 0;
+pub const STDOUT_FD: usize = 1;
+pub const STDERR_FD: usize = 2;
+
+pub fn yield_now() {
+	unsafe {
+		syscall(SYS_YIELD, 0, 0, 0, 0, 0);
+	}
+}
+
+pub fn exit(code: i32) -> ! {
+	unsafe {
+		syscall(SYS_EXIT, code as usize, 0, 0, 0, 0);
+	}
+
+	// exited task is never resumed
+	loop {}
+}
+
+pub fn sleep(ms: u32) {
+	unsafe {
+		syscall(SYS_SLEEP, ms as usize, 0, 0, 0, 0);
+	}
+}
+
+pub fn write(fd: usize, buf: &[u8]) -> isize {
+	unsafe {
+		syscall(SYS_WRITE, fd, buf.as_ptr() as usize, buf.len(), 0, 0)
+	}
+}
+
+pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+	unsafe {
+		syscall(SYS_READ, fd, buf.as_mut_ptr() as usize, buf.len(), 0, 0)
+	}
+}
+
+pub fn getpid() -> usize {
+	unsafe {
+		syscall(SYS_GETPID, 0, 0, 0, 0, 0) as usize
+	}
+}
+
+// starts new thread sharing address space with the calling task on the given stack, returns thread id
+pub fn spawn(entry: extern fn() -> !, stack: &'static mut [u8]) -> isize {
+	let stack_top = (stack.as_mut_ptr() as usize + stack.len()) & !(mem::size_of::<u32>() - 1);
+
+	unsafe {
+		syscall(SYS_SPAWN, entry as usize, stack_top, 0, 0, 0)
+	}
+}
+
+// extends heap up to specified address, returns new (page aligned) program break
+pub fn brk(addr: usize) -> usize {
+	unsafe {
+		syscall(SYS_BRK, addr, 0, 0, 0, 0) as usize
+	}
+}
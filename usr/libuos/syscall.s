@@ -0,0 +1,29 @@
+.code32
+
+.set SYSCALL_VEC_NUM, 0x80
+
+.text
+
+# system call with up to 5 arguments: syscall(num, a1, a2, a3, a4, a5)
+.global syscall
+syscall:
+
+# saving callee saved registers used for arguments passing
+pushl %ebx
+pushl %esi
+pushl %edi
+
+movl 16(%esp), %eax
+movl 20(%esp), %ebx
+movl 24(%esp), %ecx
+movl 28(%esp), %edx
+movl 32(%esp), %esi
+movl 36(%esp), %edi
+
+int $SYSCALL_VEC_NUM
+
+popl %edi
+popl %esi
+popl %ebx
+
+ret